@@ -0,0 +1,714 @@
+use crate::error::TaskRunnerError;
+use crate::fingerprint::{expand_globs, to_hex};
+use colored::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Directory, relative to the project, where cached task outputs are stored
+pub const CACHE_DIR: &str = ".task-runner/cache";
+
+/// Prefix of task outputs inside a cache artifact
+const OUTPUTS_PREFIX: &str = "outputs";
+
+/// Name of the captured log inside a cache artifact
+const LOG_ENTRY: &str = "task.log";
+
+/// Compute the cache key of a task run.
+///
+/// The key combines the task's fingerprint (sources, commands, env, working
+/// directory and shell), the output of its `tool_versions` commands and its
+/// output patterns.
+pub fn cache_key(fingerprint: &str, tool_versions: &[String], outputs: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    let mut write = |value: &str| {
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value.as_bytes());
+    };
+
+    write(fingerprint);
+    for version in tool_versions {
+        write(version);
+    }
+    for pattern in outputs {
+        write(pattern);
+    }
+
+    to_hex(&hasher.finalize())
+}
+
+/// Which stream a chunk of captured output came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Output captured from a task's commands, in the order it was written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedLog {
+    chunks: Vec<(LogStream, Vec<u8>)>,
+}
+
+impl CapturedLog {
+    pub fn push(&mut self, stream: LogStream, data: &[u8]) {
+        match self.chunks.last_mut() {
+            Some((last, chunk)) if *last == stream => chunk.extend_from_slice(data),
+            _ => self.chunks.push((stream, data.to_vec())),
+        }
+    }
+
+    /// Write the captured output back to stdout and stderr
+    pub fn replay(&self) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        let mut stderr = std::io::stderr();
+
+        for (stream, data) in &self.chunks {
+            match stream {
+                LogStream::Stdout => stdout.write_all(data)?,
+                LogStream::Stderr => stderr.write_all(data)?,
+            }
+        }
+
+        stdout.flush()?;
+        stderr.flush()
+    }
+
+    /// Encode as a sequence of `[stream][length][data]` records
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (stream, data) in &self.chunks {
+            bytes.push(match stream {
+                LogStream::Stdout => 1,
+                LogStream::Stderr => 2,
+            });
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    fn decode(mut bytes: &[u8]) -> Result<Self, TaskRunnerError> {
+        let corrupt = || TaskRunnerError::CacheError("corrupt task log in cache artifact".to_string());
+        let mut log = CapturedLog::default();
+
+        while let Some((&tag, rest)) = bytes.split_first() {
+            let stream = match tag {
+                1 => LogStream::Stdout,
+                2 => LogStream::Stderr,
+                _ => return Err(corrupt()),
+            };
+            let (len, rest) = rest.split_first_chunk::<8>().ok_or_else(corrupt)?;
+            let len = usize::try_from(u64::from_le_bytes(*len)).map_err(|_| corrupt())?;
+            if rest.len() < len {
+                return Err(corrupt());
+            }
+            log.chunks.push((stream, rest[..len].to_vec()));
+            bytes = &rest[len..];
+        }
+
+        Ok(log)
+    }
+}
+
+/// Pack the files matching `outputs` (relative to `base`) and the captured log into an artifact
+pub fn pack(base: &Path, outputs: &[String], log: &CapturedLog) -> Result<Vec<u8>, TaskRunnerError> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    for path in expand_globs(base, outputs)? {
+        let relative = path.strip_prefix(base).unwrap_or(&path);
+        builder.append_path_with_name(&path, Path::new(OUTPUTS_PREFIX).join(relative))?;
+    }
+
+    let log = log.encode();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(log.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, LOG_ENTRY, log.as_slice())?;
+
+    let encoder = builder.into_inner()?;
+    Ok(encoder.finish()?)
+}
+
+/// Restore the outputs in an artifact under `base`, returning the captured log
+pub fn unpack(base: &Path, artifact: &[u8]) -> Result<CapturedLog, TaskRunnerError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(artifact));
+    archive.set_preserve_mtime(true);
+    let mut log = CapturedLog::default();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if path == Path::new(LOG_ENTRY) {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            log = CapturedLog::decode(&bytes)?;
+            continue;
+        }
+
+        let relative = path.strip_prefix(OUTPUTS_PREFIX)
+            .map_err(|_| TaskRunnerError::CacheError(
+                format!("unexpected entry '{}' in cache artifact", path.display())
+            ))?;
+
+        // Never write outside of the task's directory, which links could point out of
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(TaskRunnerError::CacheError(
+                format!("unsafe path '{}' in cache artifact", path.display())
+            ));
+        }
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(TaskRunnerError::CacheError(format!(
+                "unsupported entry '{}' in cache artifact: only files and directories are restored",
+                path.display()
+            )));
+        }
+
+        let destination = base.join(relative);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&destination)?;
+    }
+
+    Ok(log)
+}
+
+/// Size and number of entries in a cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Storage for cache artifacts, addressed by cache key
+pub trait CacheBackend: Send + Sync {
+    /// Where the artifacts are stored, for messages
+    fn name(&self) -> String;
+
+    /// Load the artifact stored under `key`, if any
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TaskRunnerError>;
+
+    /// Store an artifact under `key`
+    fn put(&self, key: &str, artifact: &[u8]) -> Result<(), TaskRunnerError>;
+
+    /// Remove the artifact stored under `key`, if any
+    fn remove(&self, key: &str) -> Result<(), TaskRunnerError>;
+}
+
+/// Cache artifacts stored in a local directory, one file per key
+#[derive(Debug, Clone)]
+pub struct LocalCache {
+    dir: PathBuf,
+}
+
+impl LocalCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn stats(&self) -> Result<CacheStats, TaskRunnerError> {
+        let mut stats = CacheStats::default();
+        for (_, size, _) in self.entries()? {
+            stats.entries += 1;
+            stats.bytes += size;
+        }
+        Ok(stats)
+    }
+
+    /// Remove entries not used within `max_age`, then the least recently used
+    /// entries until the cache fits in `max_bytes`. Returns what was removed.
+    pub fn prune(
+        &self,
+        max_age: Option<Duration>,
+        max_bytes: Option<u64>,
+    ) -> Result<CacheStats, TaskRunnerError> {
+        let mut entries = self.entries()?;
+        // Most recently used first
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.2));
+
+        let now = SystemTime::now();
+        let mut kept_bytes = 0;
+        let mut removed = CacheStats::default();
+
+        for (path, size, modified) in entries {
+            let too_old = max_age.is_some_and(|max_age| {
+                now.duration_since(modified).unwrap_or_default() > max_age
+            });
+            let too_big = max_bytes.is_some_and(|max_bytes| kept_bytes + size > max_bytes);
+
+            if too_old || too_big {
+                std::fs::remove_file(&path)?;
+                removed.entries += 1;
+                removed.bytes += size;
+            } else {
+                kept_bytes += size;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Remove every entry, returning what was removed
+    pub fn clear(&self) -> Result<CacheStats, TaskRunnerError> {
+        let stats = self.stats()?;
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(stats)
+    }
+
+    /// Artifacts are sharded by the first two characters of their key
+    fn path(&self, key: &str) -> PathBuf {
+        let shard = key.get(..2).unwrap_or(key);
+        self.dir.join(shard).join(format!("{}.tar.gz", key))
+    }
+
+    /// Every artifact with its size and last use
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>, TaskRunnerError> {
+        let mut entries = Vec::new();
+        if !self.dir.exists() {
+            return Ok(entries);
+        }
+
+        for shard in std::fs::read_dir(&self.dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(shard.path())? {
+                let entry = entry?;
+                let path = entry.path();
+                if path.to_string_lossy().ends_with(".tar.gz") {
+                    let metadata = entry.metadata()?;
+                    entries.push((path, metadata.len(), metadata.modified()?));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl CacheBackend for LocalCache {
+    fn name(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TaskRunnerError> {
+        let path = self.path(key);
+        match std::fs::read(&path) {
+            Ok(artifact) => {
+                // Record the use so that pruning by age keeps artifacts that are still hit
+                let _ = std::fs::File::options()
+                    .append(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                Ok(Some(artifact))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, artifact: &[u8]) -> Result<(), TaskRunnerError> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so that readers never see a partial artifact
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, artifact)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), TaskRunnerError> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cache artifacts stored on an HTTP server.
+///
+/// The protocol is a plain `GET <url>/<key>` to download an artifact (404 when
+/// missing), `PUT <url>/<key>` to upload one and `DELETE <url>/<key>` to evict
+/// one that is corrupt, so any static file server that accepts uploads will do.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl HttpCache {
+    /// Requests time out after this long
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            token: None,
+            agent: ureq::AgentBuilder::new().timeout(Self::TIMEOUT).build(),
+        }
+    }
+
+    /// Send `token` as a bearer token with every request
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    fn request(&self, method: &str, key: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}/{}", self.url, key));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+impl CacheBackend for HttpCache {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TaskRunnerError> {
+        match self.request("GET", key).call() {
+            Ok(response) => {
+                let mut artifact = Vec::new();
+                response.into_reader().read_to_end(&mut artifact)?;
+                Ok(Some(artifact))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(TaskRunnerError::CacheError(format!("GET {}/{}: {}", self.url, key, e))),
+        }
+    }
+
+    fn put(&self, key: &str, artifact: &[u8]) -> Result<(), TaskRunnerError> {
+        self.request("PUT", key)
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(artifact)
+            .map_err(|e| TaskRunnerError::CacheError(format!("PUT {}/{}: {}", self.url, key, e)))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), TaskRunnerError> {
+        match self.request("DELETE", key).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(TaskRunnerError::CacheError(format!("DELETE {}/{}: {}", self.url, key, e))),
+        }
+    }
+}
+
+/// Marks the start of an artifact as stored in a backend
+const ENVELOPE_MAGIC: &[u8; 4] = b"TRC1";
+
+/// Prefix an artifact with its digest so that it can be verified when loaded
+fn seal(artifact: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(ENVELOPE_MAGIC.len() + 32 + artifact.len());
+    sealed.extend_from_slice(ENVELOPE_MAGIC);
+    sealed.extend_from_slice(&Sha256::digest(artifact));
+    sealed.extend_from_slice(artifact);
+    sealed
+}
+
+/// Check an artifact's digest, returning the artifact
+fn open(sealed: &[u8]) -> Option<&[u8]> {
+    let rest = sealed.strip_prefix(ENVELOPE_MAGIC)?;
+    let (digest, artifact) = rest.split_at_checked(32)?;
+    (Sha256::digest(artifact).as_slice() == digest).then_some(artifact)
+}
+
+/// A backend along with whether artifacts may be uploaded to it
+struct Tier {
+    backend: Box<dyn CacheBackend>,
+    read_only: bool,
+}
+
+/// The cache used by a run: the local cache, optionally backed by remote ones.
+///
+/// Artifacts are looked up in each backend in turn, and a hit is copied into
+/// the backends before it. Every artifact is stored with its SHA-256 digest
+/// and verified when it is loaded, so a corrupt or truncated download is never
+/// restored: it is evicted from its backend when that one is writable, and the
+/// lookup goes on to the next backend.
+pub struct TaskCache {
+    tiers: Vec<Tier>,
+}
+
+impl TaskCache {
+    pub fn new(local: LocalCache) -> Self {
+        Self {
+            tiers: vec![Tier { backend: Box::new(local), read_only: false }],
+        }
+    }
+
+    /// Add a backend that is checked after the existing ones
+    pub fn with_backend<B: CacheBackend + 'static>(mut self, backend: B, read_only: bool) -> Self {
+        self.tiers.push(Tier { backend: Box::new(backend), read_only });
+        self
+    }
+
+    /// Load and verify the artifact stored under `key`
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TaskRunnerError> {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            let Some(sealed) = tier.backend.get(key)? else {
+                continue;
+            };
+
+            let Some(artifact) = open(&sealed) else {
+                eprintln!("  {} Cache artifact {} from {} failed integrity verification{}",
+                    "⚠".yellow(), key, tier.backend.name(),
+                    if tier.read_only { "" } else { ", evicting it" });
+                if !tier.read_only {
+                    tier.backend.remove(key)?;
+                }
+                continue;
+            };
+
+            // Keep a copy closer to hand for next time
+            for earlier in self.tiers[..i].iter().filter(|tier| !tier.read_only) {
+                earlier.backend.put(key, &sealed)?;
+            }
+
+            return Ok(Some(artifact.to_vec()));
+        }
+
+        Ok(None)
+    }
+
+    /// Store an artifact under `key` in every backend that isn't read-only.
+    ///
+    /// Every backend is tried; the first error is returned.
+    pub fn put(&self, key: &str, artifact: &[u8]) -> Result<(), TaskRunnerError> {
+        let sealed = seal(artifact);
+        let mut result = Ok(());
+
+        for tier in self.tiers.iter().filter(|tier| !tier.read_only) {
+            if let Err(e) = tier.backend.put(key, &sealed) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Format a size in bytes for display
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_and_unpack() {
+        let root = std::env::temp_dir().join(format!("task-runner-cache-{}", std::process::id()));
+        let source = root.join("source");
+        std::fs::create_dir_all(source.join("dist/nested")).unwrap();
+        std::fs::write(source.join("dist/app.js"), "console.log(1)").unwrap();
+        std::fs::write(source.join("dist/nested/app.css"), "body {}").unwrap();
+
+        let mut log = CapturedLog::default();
+        log.push(LogStream::Stdout, b"building\n");
+        log.push(LogStream::Stderr, b"warning\n");
+        log.push(LogStream::Stdout, b"done\n");
+
+        let artifact = pack(&source, &["dist/**/*".to_string()], &log).unwrap();
+
+        let target = root.join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        let restored = unpack(&target, &artifact).unwrap();
+
+        assert_eq!(restored, log);
+        assert_eq!(std::fs::read_to_string(target.join("dist/app.js")).unwrap(), "console.log(1)");
+        assert_eq!(std::fs::read_to_string(target.join("dist/nested/app.css")).unwrap(), "body {}");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_rejects_links() {
+        let root = std::env::temp_dir().join(format!("task-runner-cache-links-{}", std::process::id()));
+        let outside = root.join("outside");
+        let target = root.join("target");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+
+        // A link to a directory outside of the task, then a file written through it
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "outputs/dist", &outside).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "outputs/dist/owned", b"evil".as_slice()).unwrap();
+        let artifact = builder.into_inner().unwrap().finish().unwrap();
+
+        assert!(matches!(unpack(&target, &artifact), Err(TaskRunnerError::CacheError(_))));
+        assert!(!outside.join("owned").exists());
+        assert!(std::fs::symlink_metadata(target.join("dist")).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_local_cache_prune() {
+        let dir = std::env::temp_dir().join(format!("task-runner-local-{}", std::process::id()));
+        let cache = LocalCache::new(&dir);
+
+        cache.put("aaaa", &[0; 100]).unwrap();
+        cache.put("bbbb", &[0; 100]).unwrap();
+        assert_eq!(cache.get("aaaa").unwrap().map(|a| a.len()), Some(100));
+        assert_eq!(cache.get("cccc").unwrap(), None);
+        assert_eq!(cache.stats().unwrap(), CacheStats { entries: 2, bytes: 200 });
+
+        let removed = cache.prune(None, Some(150)).unwrap();
+        assert_eq!(removed.entries, 1);
+        assert_eq!(cache.stats().unwrap().entries, 1);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+    }
+
+    /// Minimal stand-in for a remote cache server, storing artifacts in memory
+    fn serve(store: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>>) -> String {
+        use std::io::{BufRead, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cache", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, body) = match method.as_str() {
+                    "PUT" => {
+                        store.lock().unwrap().insert(path, body);
+                        ("200 OK", Vec::new())
+                    }
+                    "DELETE" => {
+                        store.lock().unwrap().remove(&path);
+                        ("200 OK", Vec::new())
+                    }
+                    _ => match store.lock().unwrap().get(&path) {
+                        Some(artifact) => ("200 OK", artifact.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    },
+                };
+
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status, body.len()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_remote_cache() {
+        let store = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+        let url = serve(store.clone());
+        let dir = std::env::temp_dir().join(format!("task-runner-remote-{}", std::process::id()));
+
+        // A read-only machine never uploads
+        let reader = TaskCache::new(LocalCache::new(dir.join("reader")))
+            .with_backend(HttpCache::new(&url), true);
+        reader.put("abcd", b"artifact").unwrap();
+        assert!(store.lock().unwrap().is_empty());
+
+        // A writer uploads, and a reader downloads the artifact into its local cache
+        let writer = TaskCache::new(LocalCache::new(dir.join("writer")))
+            .with_backend(HttpCache::new(&url), false);
+        writer.put("abcd", b"artifact").unwrap();
+        assert_eq!(reader.get("abcd").unwrap().as_deref(), Some(&b"artifact"[..]));
+        assert!(LocalCache::new(dir.join("reader")).get("abcd").unwrap().is_some());
+        assert_eq!(reader.get("ef01").unwrap(), None);
+
+        // A corrupt local artifact is evicted, and the remote one used instead
+        let reader_local = LocalCache::new(dir.join("reader"));
+        let mut corrupt = reader_local.get("abcd").unwrap().unwrap();
+        corrupt.push(0);
+        reader_local.put("abcd", &corrupt).unwrap();
+        assert_eq!(reader.get("abcd").unwrap().as_deref(), Some(&b"artifact"[..]));
+        assert_ne!(reader_local.get("abcd").unwrap(), Some(corrupt.clone()));
+
+        // A corrupt artifact in the last backend is a miss, and left alone when read-only
+        store.lock().unwrap().get_mut("/cache/abcd").unwrap().push(0);
+        let fresh = TaskCache::new(LocalCache::new(dir.join("fresh")))
+            .with_backend(HttpCache::new(&url), true);
+        assert_eq!(fresh.get("abcd").unwrap(), None);
+        assert!(store.lock().unwrap().contains_key("/cache/abcd"));
+
+        // ... and evicted when writable
+        reader_local.put("abcd", &corrupt).unwrap();
+        let local_only = TaskCache::new(LocalCache::new(dir.join("reader")));
+        assert_eq!(local_only.get("abcd").unwrap(), None);
+        assert_eq!(reader_local.get("abcd").unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+}
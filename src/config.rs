@@ -1,38 +1,448 @@
+use crate::dotenv::{self, EnvFile};
 use crate::error::TaskRunnerError;
+use crate::local::{self, LocalOverrides};
+use crate::shell::Shell;
 use crate::task::Task;
+use crate::template::Template;
+use crate::utils;
+use crate::vars::Var;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Inherit from the nearest configuration file in a parent directory
+    #[serde(default)]
+    pub inherit: bool,
+    
+    /// Never inherit from configuration files above this one, even when it sets `inherit`
+    #[serde(default)]
+    pub root: bool,
+    
     /// Global environment variables
     #[serde(default)]
     pub env: HashMap<String, String>,
     
+    /// Dotenv files loaded in order before `env`
+    #[serde(default)]
+    pub env_files: Vec<EnvFile>,
+    
+    /// Fail validation when a command or working directory uses an environment
+    /// variable that is not defined (without a `${VAR:-default}`)
+    #[serde(default)]
+    pub strict_env: bool,
+    
+    /// Global template variables, used as `{{ name }}` but not exported to commands
+    #[serde(default)]
+    pub vars: HashMap<String, Var>,
+    
     /// Task definitions
     pub tasks: HashMap<String, Task>,
     
+    /// Configuration files whose tasks are imported, optionally under a namespace
+    #[serde(default)]
+    pub include: Vec<Include>,
+    
+    /// Every file included, directly or not, while loading the configuration
+    #[serde(skip)]
+    pub included_files: Vec<PathBuf>,
+    
     /// Default timeout for all tasks (in seconds)
     pub default_timeout: Option<u64>,
     
     /// Default working directory
     pub default_working_dir: Option<String>,
+    
+    /// Maximum number of commands running at once (defaults to the number of CPUs)
+    pub max_parallel: Option<usize>,
+    
+    /// Capacity of named resource locks (locks not listed here are mutexes)
+    #[serde(default)]
+    pub locks: HashMap<String, usize>,
+    
+    /// Time to wait after signalling a command before killing it (in seconds)
+    pub kill_grace_period: Option<u64>,
+    
+    /// Shell used to run commands (e.g. `bash -euo pipefail`, or `exec` to run commands directly)
+    pub shell: Option<String>,
+    
+    /// Task output cache settings
+    #[serde(default)]
+    pub cache: CacheConfig,
+    
+    /// Environment profiles (e.g. `staging`, `ci`), selected with `--env`
+    #[serde(default)]
+    pub environments: HashMap<String, Environment>,
+    
+    /// Name of the environment profile applied to this configuration
+    #[serde(skip)]
+    pub environment: Option<String>,
+    
+    /// Directory of the configuration file, which `default_working_dir` and
+    /// `env_files` are resolved against (empty for the current directory)
+    #[serde(skip)]
+    pub dir: PathBuf,
+    
+    /// Configuration files in parent directories merged underneath this one, nearest first
+    #[serde(skip)]
+    pub parent_configs: Vec<PathBuf>,
+    
+    /// The user configuration merged underneath this one, if any
+    #[serde(skip)]
+    pub user_config: Option<PathBuf>,
+    
+    /// What the local override file laid over this configuration, if there is one
+    #[serde(skip)]
+    pub local_overrides: Option<LocalOverrides>,
+}
+
+/// A configuration file to import tasks from: a path, or `{ path, namespace }`
+/// to import its tasks as `namespace:task`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Include {
+    Path(String),
+    Detailed(IncludeDetails),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeDetails {
+    /// Path of the file, relative to the including file
+    pub path: String,
+    
+    /// Prefix of the imported task names
+    pub namespace: Option<String>,
+}
+
+impl Include {
+    pub fn path(&self) -> &str {
+        match self {
+            Include::Path(path) => path,
+            Include::Detailed(details) => &details.path,
+        }
+    }
+    
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            Include::Path(_) => None,
+            Include::Detailed(details) => details.namespace.as_deref(),
+        }
+    }
+}
+
+/// Settings an environment profile lays over the rest of the configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    /// Environment variables added to the global ones, replacing those with the same name
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    
+    /// Dotenv files loaded after the global ones
+    #[serde(default)]
+    pub env_files: Vec<EnvFile>,
+    
+    /// Template variables added to the global ones, replacing those with the same name
+    #[serde(default)]
+    pub vars: HashMap<String, Var>,
+    
+    /// Default timeout for all tasks (in seconds)
+    pub default_timeout: Option<u64>,
+    
+    /// Settings of individual tasks
+    #[serde(default)]
+    pub tasks: HashMap<String, TaskOverrides>,
+}
+
+/// Task settings replaced by an environment profile
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskOverrides {
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    
+    #[serde(default)]
+    pub env_files: Vec<EnvFile>,
+    
+    #[serde(default)]
+    pub vars: HashMap<String, Var>,
+    
+    pub timeout: Option<u64>,
+    
+    pub working_dir: Option<String>,
+}
+
+/// Task output cache settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Cache shared between machines, checked after the local cache
+    pub remote: Option<RemoteCacheConfig>,
+}
+
+/// An HTTP server storing cache artifacts with `GET`/`PUT <url>/<key>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCacheConfig {
+    /// Base URL of the cache server
+    pub url: String,
+    
+    /// Only download artifacts, never upload them
+    #[serde(default)]
+    pub read_only: bool,
+    
+    /// Environment variable holding a bearer token for the server
+    pub token_env: Option<String>,
+}
+
+impl RemoteCacheConfig {
+    /// Environment variable overriding `read_only`, e.g. to let CI upload
+    pub const READ_ONLY_ENV: &'static str = "TASK_RUNNER_CACHE_READ_ONLY";
+    
+    /// Whether uploads are disabled, taking the environment override into account
+    pub fn is_read_only(&self) -> bool {
+        match std::env::var(Self::READ_ONLY_ENV).as_deref() {
+            Ok("1") | Ok("true") => true,
+            Ok("0") | Ok("false") => false,
+            _ => self.read_only,
+        }
+    }
+}
+
+/// Names of configuration files, in the order they are looked for in a directory
+const CONFIG_NAMES: [&str; 4] = [
+    "task-runner.json",
+    "task-runner.yaml",
+    "task-runner.yml",
+    "task-runner.toml",
+];
+
+/// Names of the user configuration file in the user's config directory
+const USER_CONFIG_NAMES: [&str; 4] = [
+    "task-runner/config.json",
+    "task-runner/config.yaml",
+    "task-runner/config.yml",
+    "task-runner/config.toml",
+];
+
+/// How to load a configuration file
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Environment profile to apply
+    pub environment: Option<String>,
+    
+    /// Merge the user configuration underneath the project configuration (the default)
+    pub user_config: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            environment: None,
+            user_config: true,
+        }
+    }
 }
 
 impl Config {
+    /// Environment variable naming the configuration file when `--config` isn't given
+    pub const CONFIG_ENV: &'static str = "TASK_RUNNER_CONFIG";
+    
+    /// Environment variable selecting the environment profile when `--env` isn't given
+    pub const ENVIRONMENT_ENV: &'static str = "TASK_RUNNER_ENV";
+    
     /// Load configuration from file or search for default config files
     pub fn load(config_path: Option<&str>) -> Result<Self, TaskRunnerError> {
+        Self::load_with_path(config_path, &LoadOptions::default()).map(|(config, _)| config)
+    }
+    
+    /// Like `load`, with `options`, also returning the path of the file that was loaded
+    pub fn load_with_path(
+        config_path: Option<&str>,
+        options: &LoadOptions,
+    ) -> Result<(Self, PathBuf), TaskRunnerError> {
+        let path = Self::locate(config_path)?;
+        Ok((Self::load_from_file_with_options(&path, options)?, path))
+    }
+    
+    /// The configuration file to use: `config_path` if given, or else the
+    /// nearest one in the current directory or its parents
+    pub fn locate(config_path: Option<&str>) -> Result<PathBuf, TaskRunnerError> {
         if let Some(path) = config_path {
-            Self::load_from_file(path)
-        } else {
-            Self::load_default()
+            return Ok(PathBuf::from(path));
         }
+        
+        let current_dir = std::env::current_dir()?;
+        let path = Self::find_nearest(&current_dir).ok_or(TaskRunnerError::ConfigNotFound)?;
+        
+        // Paths in the current directory stay relative, as they always have
+        Ok(match path.strip_prefix(&current_dir) {
+            Ok(name) if path.parent() == Some(current_dir.as_path()) => name.to_path_buf(),
+            _ => path,
+        })
+    }
+    
+    /// The configuration file in `dir` or the nearest of its parents that has one
+    fn find_nearest(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors().find_map(Self::find_in)
+    }
+    
+    /// The configuration file in `dir`, if any
+    fn find_in(dir: &Path) -> Option<PathBuf> {
+        CONFIG_NAMES.iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
     }
     
     /// Load configuration from a specific file
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, TaskRunnerError> {
+        Self::load_from_file_with_options(path, &LoadOptions::default())
+    }
+    
+    /// Load configuration from a specific file, with its local override file
+    /// over it, and the files it includes, the configuration files of parent
+    /// directories and the user configuration underneath, applying the
+    /// environment profile before validating it
+    pub fn load_from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &LoadOptions,
+    ) -> Result<Self, TaskRunnerError> {
         let path = path.as_ref();
+        let mut config = Self::parse_with_includes(path)?;
+        config.inherit_parent_configs(path)?;
+        
+        if options.user_config {
+            if let Some(user_path) = Self::user_config_path() {
+                let user = Self::parse_plain(&user_path).map_err(|e| match e {
+                    TaskRunnerError::ConfigParseError(message) => TaskRunnerError::ConfigParseError(
+                        format!("{}: {}", user_path.display(), message)
+                    ),
+                    e => e,
+                })?;
+                config.merge_underneath(user, user_path.parent().unwrap_or(Path::new("")), |task| {
+                    task.from_user_config = true;
+                });
+                config.user_config = Some(user_path);
+            }
+        }
+        config.set_dir(path.parent().unwrap_or(Path::new("")));
+        
+        if let Some(name) = &options.environment {
+            config.apply_environment(name)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+    
+    /// The user configuration file (`~/.config/task-runner/config.yaml` on
+    /// Linux), if there is one
+    pub fn user_config_path() -> Option<PathBuf> {
+        let dir = dirs::config_dir()?;
+        USER_CONFIG_NAMES.iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+    
+    /// The file at `path`, with its local override file over it and the tasks
+    /// of the files it includes
+    fn parse_with_includes(path: &Path) -> Result<Self, TaskRunnerError> {
+        let mut config = Self::parse_file(path)?;
+        config.resolve_includes(path, &mut Vec::new())?;
+        Ok(config)
+    }
+    
+    /// If this configuration sets `inherit`, merge the nearest configuration
+    /// file in a parent directory of `path` underneath it, and so on for as
+    /// long as each file merged sets `inherit` too. Inheriting stops at a
+    /// `root` file and never looks above the home directory. Inherited tasks
+    /// run in their own directories.
+    fn inherit_parent_configs(&mut self, path: &Path) -> Result<(), TaskRunnerError> {
+        if !self.inherit || self.root {
+            return Ok(());
+        }
+        let path = std::env::current_dir()?.join(path);
+        let home = dirs::home_dir();
+        let parent_dirs = path.parent().into_iter()
+            .flat_map(Path::ancestors)
+            .skip(1)
+            .take_while(|dir| !home.as_ref().is_some_and(|home| home.starts_with(dir) && home != dir));
+        
+        for dir in parent_dirs {
+            let Some(parent_path) = Self::find_in(dir) else {
+                continue;
+            };
+            let mut parent = Self::parse_with_includes(&parent_path).map_err(|e| match e {
+                TaskRunnerError::ConfigParseError(message) => TaskRunnerError::ConfigParseError(
+                    format!("{}: {}", parent_path.display(), message)
+                ),
+                e => e,
+            })?;
+            parent.set_dir(dir);
+            for task in parent.tasks.values_mut() {
+                if task.working_dir.is_none() {
+                    task.working_dir = Some(parent.default_working_dir.clone().unwrap_or_else(|| ".".to_string()));
+                }
+            }
+            
+            let inherit = parent.inherit && !parent.root;
+            self.included_files.append(&mut parent.included_files);
+            self.merge_underneath(parent, dir, |task| task.inherited_from = Some(parent_path.clone()));
+            self.parent_configs.push(parent_path);
+            if !inherit {
+                break;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Lay this configuration over `other`, a configuration in `other_dir`: its
+    /// tasks, environment, variables and defaults apply unless this one sets its
+    /// own. The tasks taken from `other` are marked with `mark`, and its env
+    /// files are resolved against `other_dir`.
+    fn merge_underneath(&mut self, other: Config, other_dir: &Path, mark: impl Fn(&mut Task)) {
+        let resolve = |files: Vec<EnvFile>| -> Vec<EnvFile> {
+            files.into_iter().map(|file| file.relative_to(other_dir)).collect()
+        };
+        
+        for (name, mut task) in other.tasks {
+            if let Entry::Vacant(entry) = self.tasks.entry(name) {
+                task.env_files = resolve(task.env_files);
+                mark(&mut task);
+                entry.insert(task);
+            }
+        }
+        
+        for (key, value) in other.env {
+            self.env.entry(key).or_insert(value);
+        }
+        for (name, value) in other.vars {
+            self.vars.entry(name).or_insert(value);
+        }
+        for (lock, capacity) in other.locks {
+            self.locks.entry(lock).or_insert(capacity);
+        }
+        let mut env_files = resolve(other.env_files);
+        env_files.append(&mut self.env_files);
+        self.env_files = env_files;
+        
+        self.shell = self.shell.take().or(other.shell);
+        self.default_timeout = self.default_timeout.or(other.default_timeout);
+        self.kill_grace_period = self.kill_grace_period.or(other.kill_grace_period);
+        self.max_parallel = self.max_parallel.or(other.max_parallel);
+    }
+    
+    /// Read a configuration file, with its local override file over it if
+    /// there is one, without validating it
+    fn parse_file(path: &Path) -> Result<Self, TaskRunnerError> {
+        match local::find(path) {
+            Some(local_path) => Self::parse_with_local(path, &local_path),
+            None => Self::parse_plain(path),
+        }
+    }
+    
+    /// Read a configuration file on its own, without validating it
+    fn parse_plain(path: &Path) -> Result<Self, TaskRunnerError> {
         let content = std::fs::read_to_string(path)
             .map_err(|_| TaskRunnerError::ConfigNotFound)?;
         
@@ -47,35 +457,238 @@ impl Config {
                 "Unsupported file format. Use .json, .yaml, .yml, or .toml".to_string()
             )),
         };
+        Ok(config)
+    }
+    
+    /// Parse the configuration file at `path` with the local override file at
+    /// `local_path` deep-merged over it
+    fn parse_with_local(path: &Path, local_path: &Path) -> Result<Self, TaskRunnerError> {
+        let mut value = local::parse_value(path)?;
+        let overrides = local::merge(&mut value, local::parse_value(local_path)?, local_path)?;
         
-        config.validate()?;
+        let mut config: Config = serde_json::from_value(value).map_err(|e| TaskRunnerError::ConfigParseError(
+            format!("{} (with {}): {}", path.display(), local_path.display(), e)
+        ))?;
+        config.local_overrides = Some(overrides);
         Ok(config)
     }
     
+    /// Import the tasks of the included files, which import their own
+    /// includes first. `stack` holds the files that include `path`.
+    fn resolve_includes(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<(), TaskRunnerError> {
+        let canonical = path.canonicalize()?;
+        if let Some(start) = stack.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = stack[start..].iter().chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(TaskRunnerError::ConfigParseError(
+                format!("Include cycle: {}", cycle.join(" -> "))
+            ));
+        }
+        stack.push(canonical);
+        
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for include in self.include.clone() {
+            if let Some(namespace) = include.namespace() {
+                if !utils::is_valid_task_name(namespace) {
+                    return Err(TaskRunnerError::ConfigParseError(format!(
+                        "Invalid namespace '{}' (use letters, digits, '-' and '_')", namespace
+                    )));
+                }
+            }
+            
+            let include_path = dir.join(include.path());
+            let mut included = Self::parse_file(&include_path).map_err(|e| match e {
+                TaskRunnerError::ConfigNotFound => TaskRunnerError::ConfigParseError(
+                    format!("Included file '{}' not found", include_path.display())
+                ),
+                TaskRunnerError::ConfigParseError(message) => TaskRunnerError::ConfigParseError(
+                    format!("{}: {}", include_path.display(), message)
+                ),
+                e => e,
+            })?;
+            included.resolve_includes(&include_path, stack)?;
+            
+            self.included_files.push(include_path.canonicalize()?);
+            self.import(included, &include)?;
+        }
+        
+        stack.pop();
+        Ok(())
+    }
+    
+    /// Resolve relative paths against `dir`, the directory of the configuration file
+    fn set_dir(&mut self, dir: &Path) {
+        for task in self.tasks.values_mut() {
+            task.base_dir = dir.join(&task.base_dir);
+        }
+        self.dir = dir.to_path_buf();
+    }
+    
+    /// Add the tasks of an included configuration, renamed into its namespace
+    fn import(&mut self, included: Config, include: &Include) -> Result<(), TaskRunnerError> {
+        let prefix = Path::new(include.path()).parent().unwrap_or(Path::new(""));
+        let rename = |name: &str| match include.namespace() {
+            Some(namespace) => format!("{}:{}", namespace, name),
+            None => name.to_string(),
+        };
+        
+        for (name, mut task) in included.tasks {
+            // The included file's global settings only apply to its own tasks
+            let mut env = included.env.clone();
+            env.extend(task.env);
+            task.env = env;
+            let mut env_files = included.env_files.clone();
+            env_files.append(&mut task.env_files);
+            task.env_files = env_files;
+            let mut vars = included.vars.clone();
+            vars.extend(task.vars);
+            task.vars = vars;
+            task.shell = task.shell.or_else(|| included.shell.clone());
+            task.timeout = task.timeout.or(included.default_timeout);
+            task.kill_grace_period = task.kill_grace_period.or(included.kill_grace_period);
+            
+            // Relative paths stay relative to the included file, which is also where the task runs by default
+            task.base_dir = prefix.join(&task.base_dir);
+            if task.working_dir.is_none() {
+                task.working_dir = Some(included.default_working_dir.clone().unwrap_or_else(|| ".".to_string()));
+            }
+            task.dependencies = task.dependencies.iter().map(|dep| rename(dep)).collect();
+            
+            let name = rename(&name);
+            if self.tasks.contains_key(&name) {
+                return Err(TaskRunnerError::ConfigParseError(format!(
+                    "Task '{}' from '{}' is already defined", name, include.path()
+                )));
+            }
+            self.tasks.insert(name, task);
+        }
+        
+        for (lock, capacity) in included.locks {
+            match self.locks.get(&lock) {
+                Some(existing) if *existing != capacity => {
+                    return Err(TaskRunnerError::ConfigParseError(format!(
+                        "Lock '{}' has capacity {} here and {} in '{}'",
+                        lock, existing, capacity, include.path()
+                    )));
+                }
+                _ => {
+                    self.locks.insert(lock, capacity);
+                }
+            }
+        }
+        
+        self.included_files.extend(included.included_files);
+        Ok(())
+    }
+    
     /// Search for and load default configuration files
     pub fn load_default() -> Result<Self, TaskRunnerError> {
-        let config_names = [
-            "task-runner.json",
-            "task-runner.yaml", 
-            "task-runner.yml",
-            "task-runner.toml",
-        ];
+        Self::load(None)
+    }
+    
+    /// Lay the settings of environment profile `name` over the configuration
+    pub fn apply_environment(&mut self, name: &str) -> Result<(), TaskRunnerError> {
+        let Some(profile) = self.environments.get(name).cloned() else {
+            let mut names: Vec<&str> = self.environments.keys().map(String::as_str).collect();
+            names.sort();
+            let available = if names.is_empty() {
+                "the configuration defines no environments".to_string()
+            } else {
+                format!("available: {}", names.join(", "))
+            };
+            return Err(TaskRunnerError::ConfigParseError(
+                format!("Environment '{}' not found ({})", name, available)
+            ));
+        };
+        
+        self.env.extend(profile.env);
+        self.env_files.extend(profile.env_files);
+        self.vars.extend(profile.vars);
+        if profile.default_timeout.is_some() {
+            self.default_timeout = profile.default_timeout;
+        }
         
-        for name in &config_names {
-            if let Ok(config) = Self::load_from_file(name) {
-                return Ok(config);
+        for (task_name, overrides) in profile.tasks {
+            let task = self.tasks.get_mut(&task_name).ok_or_else(|| {
+                TaskRunnerError::ConfigParseError(format!(
+                    "Environment '{}' overrides unknown task '{}'", name, task_name
+                ))
+            })?;
+            task.env.extend(overrides.env);
+            task.env_files.extend(overrides.env_files);
+            task.vars.extend(overrides.vars);
+            if overrides.timeout.is_some() {
+                task.timeout = overrides.timeout;
+            }
+            if overrides.working_dir.is_some() {
+                task.working_dir = overrides.working_dir;
             }
         }
         
-        Err(TaskRunnerError::ConfigNotFound)
+        self.environment = Some(name.to_string());
+        Ok(())
+    }
+    
+    /// The applied environment profile, with its name
+    pub fn active_environment(&self) -> Option<(&str, &Environment)> {
+        let name = self.environment.as_deref()?;
+        self.environments.get(name).map(|profile| (name, profile))
     }
     
     /// Validate configuration
     pub fn validate(&self) -> Result<(), TaskRunnerError> {
+        if let Some((lock, _)) = self.locks.iter().find(|(_, capacity)| **capacity == 0) {
+            return Err(TaskRunnerError::ConfigParseError(
+                format!("Lock '{}' must have a capacity of at least 1", lock)
+            ));
+        }
+        
+        if self.max_parallel == Some(0) {
+            return Err(TaskRunnerError::ConfigParseError(
+                "max_parallel must be at least 1".to_string()
+            ));
+        }
+        
+        if let Some(shell) = &self.shell {
+            Shell::parse(shell).map_err(TaskRunnerError::ConfigParseError)?;
+        }
+        
+        if let Some(remote) = &self.cache.remote {
+            if !remote.url.starts_with("http://") && !remote.url.starts_with("https://") {
+                return Err(TaskRunnerError::ConfigParseError(
+                    format!("Remote cache URL '{}' must start with http:// or https://", remote.url)
+                ));
+            }
+        }
+        
+        if self.vars.contains_key("params") {
+            return Err(TaskRunnerError::ConfigParseError(
+                "'params' is reserved and cannot be used as a variable name".to_string()
+            ));
+        }
+        
+        for (name, profile) in &self.environments {
+            if let Some(task_name) = profile.tasks.keys().find(|task| !self.tasks.contains_key(*task)) {
+                return Err(TaskRunnerError::ConfigParseError(format!(
+                    "Environment '{}' overrides unknown task '{}'", name, task_name
+                )));
+            }
+            let reserved = profile.vars.contains_key("params")
+                || profile.tasks.values().any(|task| task.vars.contains_key("params"));
+            if reserved {
+                return Err(TaskRunnerError::ConfigParseError(format!(
+                    "Environment '{}': 'params' is reserved and cannot be used as a variable name", name
+                )));
+            }
+        }
+        
         // Validate each task
         for (name, task) in &self.tasks {
             task.validate(name)
-                .map_err(|e| TaskRunnerError::ConfigParseError(e))?;
+                .map_err(TaskRunnerError::ConfigParseError)?;
+            self.check_template_vars(name, task)?;
+            self.check_env_vars(name, task)?;
         }
         
         // Check for circular dependencies
@@ -87,16 +700,65 @@ impl Config {
         Ok(())
     }
     
+    /// Check that the templates of a task only use variables that are defined,
+    /// unless they fall back to a default
+    fn check_template_vars(&self, name: &str, task: &Task) -> Result<(), TaskRunnerError> {
+        for template in task.templates() {
+            let template = Template::parse(template)?;
+            let undefined = template.references().find(|reference| {
+                let var = reference.path[0].as_str();
+                !reference.has_default && var != "params"
+                    && !task.vars.contains_key(var) && !self.vars.contains_key(var)
+            });
+            if let Some(reference) = undefined {
+                return Err(TaskRunnerError::ConfigParseError(format!(
+                    "Task '{}' uses undefined variable '{}'", name, reference.path[0]
+                )));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Check the environment variable references of a task's commands and
+    /// working directory, and in strict mode that every variable they need is
+    /// defined (which reads the env files)
+    fn check_env_vars(&self, name: &str, task: &Task) -> Result<(), TaskRunnerError> {
+        let working_dir = task.working_dir.as_deref().or(self.default_working_dir.as_deref());
+        let strings = task.commands.iter().map(|command| command.run()).chain(working_dir);
+        let env = if self.strict_env {
+            dotenv::resolve(self, task)?
+        } else {
+            HashMap::new()
+        };
+        
+        for string in strings {
+            let required = utils::required_env_vars(string).map_err(|e| {
+                TaskRunnerError::ConfigParseError(format!("Task '{}': {}", name, e))
+            })?;
+            
+            if !self.strict_env {
+                continue;
+            }
+            let undefined = required.iter().find(|var| !env.contains_key(*var));
+            if let Some(var) = undefined {
+                return Err(TaskRunnerError::ConfigParseError(format!(
+                    "Task '{}' uses undefined environment variable '{}'", name, var
+                )));
+            }
+        }
+        Ok(())
+    }
+    
     /// Check for circular dependencies using DFS
     fn check_circular_dependencies(&self) -> Result<(), TaskRunnerError> {
         let mut visited = std::collections::HashSet::new();
         let mut rec_stack = std::collections::HashSet::new();
         
         for task_name in self.tasks.keys() {
-            if !visited.contains(task_name) {
-                if self.has_circular_dependency(task_name, &mut visited, &mut rec_stack) {
-                    return Err(TaskRunnerError::CircularDependency(task_name.clone()));
-                }
+            if !visited.contains(task_name)
+                && self.has_circular_dependency(task_name, &mut visited, &mut rec_stack)
+            {
+                return Err(TaskRunnerError::CircularDependency(task_name.clone()));
             }
         }
         
@@ -143,6 +805,22 @@ impl Config {
         Ok(())
     }
     
+    /// The directory a task runs in, with environment variables expanded and
+    /// relative paths resolved against the file defining the task
+    pub fn working_dir(
+        &self,
+        task: &Task,
+        env: &HashMap<String, String>,
+    ) -> Result<Option<String>, TaskRunnerError> {
+        let (base, dir) = match (&task.working_dir, &self.default_working_dir) {
+            (Some(dir), _) => (&task.base_dir, dir.as_str()),
+            (None, Some(dir)) => (&self.dir, dir.as_str()),
+            (None, None) if task.base_dir.as_os_str().is_empty() => return Ok(None),
+            (None, None) => (&task.base_dir, "."),
+        };
+        Ok(Some(utils::join_path(base, &utils::expand_env_vars(dir, env)?)))
+    }
+    
     /// Get task by name
     pub fn get_task(&self, name: &str) -> Option<&Task> {
         self.tasks.get(name)
@@ -157,6 +835,17 @@ impl Config {
             .collect()
     }
     
+    /// Get the names of all visible service tasks, sorted
+    pub fn get_services(&self) -> Vec<String> {
+        let mut services: Vec<String> = self.tasks
+            .iter()
+            .filter(|(_, task)| task.service && !task.hidden)
+            .map(|(name, _)| name.clone())
+            .collect();
+        services.sort();
+        services
+    }
+    
     /// Get task dependencies in execution order
     pub fn get_execution_order(&self, task_names: &[String]) -> Result<Vec<String>, TaskRunnerError> {
         let mut order = Vec::new();
@@ -194,4 +883,287 @@ impl Config {
         
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    
+    /// Held by tests loading configuration files, which read the user configuration
+    /// from `XDG_CONFIG_HOME`, while `test_load_merges_user_config` changes it
+    static USER_CONFIG_DIR: Mutex<()> = Mutex::new(());
+    
+    #[test]
+    fn test_apply_environment() {
+        let mut config: Config = serde_yaml::from_str(r#"
+env:
+  API_URL: http://localhost:8080
+  LOG_LEVEL: debug
+default_timeout: 60
+tasks:
+  deploy:
+    env:
+      REPLICAS: "1"
+    commands:
+      - ./deploy.sh
+environments:
+  staging:
+    env:
+      API_URL: https://staging.example.com
+    default_timeout: 300
+    tasks:
+      deploy:
+        env:
+          REPLICAS: "2"
+        working_dir: infra/staging
+"#).unwrap();
+        config.validate().unwrap();
+        
+        config.apply_environment("staging").unwrap();
+        assert_eq!(config.env["API_URL"], "https://staging.example.com");
+        assert_eq!(config.env["LOG_LEVEL"], "debug");
+        assert_eq!(config.default_timeout, Some(300));
+        
+        let deploy = &config.tasks["deploy"];
+        assert_eq!(deploy.env["REPLICAS"], "2");
+        assert_eq!(deploy.working_dir.as_deref(), Some("infra/staging"));
+        assert_eq!(config.active_environment().map(|(name, _)| name), Some("staging"));
+        
+        assert!(config.apply_environment("production").is_err());
+        
+        config.environments.get_mut("staging").unwrap().tasks.insert("missing".to_string(), Default::default());
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_includes() {
+        let _guard = USER_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("task-runner-include-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("backend/db")).unwrap();
+        std::fs::write(root.join("task-runner.yaml"), r#"
+include:
+  - { path: backend/task-runner.yaml, namespace: backend }
+tasks:
+  all:
+    dependencies: [backend:build]
+    commands: ["true"]
+"#).unwrap();
+        std::fs::write(root.join("backend/task-runner.yaml"), r#"
+env:
+  SERVICE: backend
+include:
+  - { path: db/task-runner.yaml, namespace: db }
+tasks:
+  build:
+    dependencies: [db:migrate]
+    working_dir: ./src
+    commands: ["true"]
+"#).unwrap();
+        std::fs::write(root.join("backend/db/task-runner.yaml"), "tasks: { migrate: { commands: [\"true\"] } }").unwrap();
+        
+        let config = Config::load_from_file(root.join("task-runner.yaml")).unwrap();
+        let build = &config.tasks["backend:build"];
+        assert_eq!(build.dependencies, vec!["backend:db:migrate"]);
+        assert_eq!(build.base_dir, root.join("backend"));
+        assert_eq!(config.working_dir(build, &HashMap::new()).unwrap(), Some(root.join("backend/src").display().to_string()));
+        assert_eq!(build.env["SERVICE"], "backend");
+        let migrate = &config.tasks["backend:db:migrate"];
+        assert_eq!(config.working_dir(migrate, &HashMap::new()).unwrap(), Some(root.join("backend/db").display().to_string()));
+        assert_eq!(config.included_files.len(), 2);
+        
+        // Tasks run in the directory of the file defining them
+        let all = &config.tasks["all"];
+        assert_eq!(config.working_dir(all, &HashMap::new()).unwrap(), Some(root.display().to_string()));
+        
+        // A file including one of the files that include it
+        std::fs::write(root.join("backend/db/task-runner.yaml"), "include: [../task-runner.yaml]\ntasks: {}").unwrap();
+        let error = Config::load_from_file(root.join("task-runner.yaml")).unwrap_err();
+        assert!(error.to_string().contains("Include cycle"));
+        
+        std::fs::write(root.join("backend/db/task-runner.yaml"), "tasks: {}").unwrap();
+        std::fs::write(root.join("task-runner.yaml"), r#"
+include:
+  - { path: backend/task-runner.yaml, namespace: backend }
+tasks:
+  backend:build:
+    commands: ["true"]
+"#).unwrap();
+        assert!(Config::load_from_file(root.join("task-runner.yaml")).is_err());
+        
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn test_included_local_override() {
+        let _guard = USER_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("task-runner-include-local-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("backend")).unwrap();
+        std::fs::write(root.join("task-runner.yaml"), r#"
+include:
+  - { path: backend/task-runner.yaml, namespace: backend }
+tasks: {}
+"#).unwrap();
+        std::fs::write(root.join("backend/task-runner.yaml"), r#"
+env:
+  PORT: "8080"
+tasks:
+  build:
+    commands: ["cargo build"]
+"#).unwrap();
+        std::fs::write(root.join("backend/task-runner.local.yaml"), r#"
+env:
+  PORT: "3000"
+tasks:
+  build:
+    commands: ["cargo build --offline"]
+"#).unwrap();
+        
+        // The override next to an included file applies to that file's tasks
+        let config = Config::load_from_file(root.join("task-runner.yaml")).unwrap();
+        let build = &config.tasks["backend:build"];
+        assert_eq!(build.commands[0].run(), "cargo build --offline");
+        assert_eq!(build.env["PORT"], "3000");
+        assert!(config.local_overrides.is_none());
+        
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[test]
+    fn test_merge_user_config() {
+        let mut config: Config = serde_yaml::from_str(r#"
+env:
+  LOG_LEVEL: debug
+default_timeout: 60
+tasks:
+  build:
+    commands:
+      - cargo build
+"#).unwrap();
+        let user: Config = serde_yaml::from_str(r#"
+env:
+  LOG_LEVEL: warn
+  EDITOR: vim
+env_files:
+  - secrets.env
+default_timeout: 10
+shell: zsh
+tasks:
+  build:
+    commands:
+      - make
+  clean-all:
+    description: Remove build output
+    commands:
+      - git clean -fdx
+"#).unwrap();
+        
+        config.merge_underneath(user, Path::new("/home/user/.config/task-runner"), |task| {
+            task.from_user_config = true;
+        });
+        config.validate().unwrap();
+        
+        assert_eq!(config.env["LOG_LEVEL"], "debug");
+        assert_eq!(config.env["EDITOR"], "vim");
+        assert_eq!(config.default_timeout, Some(60));
+        assert_eq!(config.shell.as_deref(), Some("zsh"));
+        assert_eq!(config.env_files[0].path(), "/home/user/.config/task-runner/secrets.env");
+        
+        assert_eq!(config.tasks["build"].commands[0].run(), "cargo build");
+        assert!(!config.tasks["build"].from_user_config);
+        assert!(config.tasks["clean-all"].from_user_config);
+    }
+    
+    #[test]
+    fn test_parent_configs() {
+        let _guard = USER_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("task-runner-parents-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("repo/backend/src")).unwrap();
+        std::fs::create_dir_all(root.join("repo/docs")).unwrap();
+        std::fs::create_dir_all(root.join("repo/web")).unwrap();
+        std::fs::write(root.join("task-runner.yaml"), "tasks: { outside: { commands: [\"true\"] } }").unwrap();
+        std::fs::write(root.join("repo/task-runner.yaml"), r#"
+env:
+  LOG_LEVEL: info
+  REGISTRY: local
+tasks:
+  lint:
+    commands: ["cargo clippy"]
+  test:
+    commands: ["cargo test"]
+"#).unwrap();
+        std::fs::write(root.join("repo/backend/task-runner.yaml"), r#"
+inherit: true
+env:
+  LOG_LEVEL: debug
+tasks:
+  test:
+    commands: ["cargo test -p backend"]
+"#).unwrap();
+        
+        std::fs::write(root.join("repo/web/task-runner.yaml"), "tasks: { serve: { commands: [\"true\"] } }").unwrap();
+        
+        // The nearest configuration is found from any directory below it
+        assert_eq!(Config::find_nearest(&root.join("repo/backend/src")), Some(root.join("repo/backend/task-runner.yaml")));
+        assert_eq!(Config::find_nearest(&root.join("repo/docs")), Some(root.join("repo/task-runner.yaml")));
+        
+        // Parent configurations are inherited for as long as each file sets `inherit`
+        let config = Config::load_from_file(root.join("repo/backend/task-runner.yaml")).unwrap();
+        assert_eq!(config.parent_configs, vec![root.join("repo/task-runner.yaml")]);
+        assert!(!config.tasks.contains_key("outside"));
+        assert_eq!(config.env["LOG_LEVEL"], "debug");
+        assert_eq!(config.env["REGISTRY"], "local");
+        assert_eq!(config.tasks["test"].commands[0].run(), "cargo test -p backend");
+        assert_eq!(config.tasks["test"].inherited_from, None);
+        
+        // Inherited tasks run in the directory of the file defining them
+        let lint = &config.tasks["lint"];
+        assert_eq!(lint.inherited_from, Some(root.join("repo/task-runner.yaml")));
+        assert_eq!(config.working_dir(lint, &HashMap::new()).unwrap(), Some(root.join("repo").display().to_string()));
+        
+        // Configurations without `inherit` ignore unrelated ones further up
+        let config = Config::load_from_file(root.join("repo/web/task-runner.yaml")).unwrap();
+        assert!(config.parent_configs.is_empty());
+        assert!(!config.tasks.contains_key("lint"));
+        assert!(!config.tasks.contains_key("outside"));
+        
+        // A `root: true` configuration inherits nothing, even with `inherit`
+        std::fs::write(root.join("repo/task-runner.yaml"), "inherit: true\nroot: true\ntasks: {}").unwrap();
+        let config = Config::load_from_file(root.join("repo/task-runner.yaml")).unwrap();
+        assert!(config.parent_configs.is_empty());
+        assert!(!config.tasks.contains_key("outside"));
+        
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_load_merges_user_config() {
+        let _guard = USER_CONFIG_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("task-runner-user-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("xdg/task-runner")).unwrap();
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        std::fs::write(root.join("xdg/task-runner/config.yaml"), "tasks: { clean-all: { commands: [\"git clean -fdx\"] } }").unwrap();
+        std::fs::write(root.join("project/task-runner.yaml"), "tasks: { build: { commands: [\"make\"] } }").unwrap();
+        
+        let previous = std::env::var_os("XDG_CONFIG_HOME");
+        std::env::set_var("XDG_CONFIG_HOME", root.join("xdg"));
+        let project = root.join("project/task-runner.yaml");
+        let loaded = Config::load(Some(project.to_str().unwrap()));
+        let without_user = Config::load_from_file_with_options(
+            &project,
+            &LoadOptions { user_config: false, ..Default::default() },
+        );
+        match previous {
+            Some(dir) => std::env::set_var("XDG_CONFIG_HOME", dir),
+            None => std::env::remove_var("XDG_CONFIG_HOME"),
+        }
+        
+        let config = loaded.unwrap();
+        assert!(config.tasks["clean-all"].from_user_config);
+        assert_eq!(config.user_config, Some(root.join("xdg/task-runner/config.yaml")));
+        assert!(!without_user.unwrap().tasks.contains_key("clean-all"));
+        
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
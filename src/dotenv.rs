@@ -0,0 +1,308 @@
+use crate::config::Config;
+use crate::error::TaskRunnerError;
+use crate::task::Task;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A dotenv file to load environment variables from: a path, or
+/// `{ path, optional }` for a file that may not exist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvFile {
+    Path(String),
+    Detailed(EnvFileDetails),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvFileDetails {
+    pub path: String,
+
+    /// Skip the file when it doesn't exist instead of failing
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl EnvFile {
+    pub fn path(&self) -> &str {
+        match self {
+            EnvFile::Path(path) => path,
+            EnvFile::Detailed(details) => &details.path,
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        matches!(self, EnvFile::Detailed(details) if details.optional)
+    }
+
+    /// The same file with a relative path resolved against `dir`
+    pub fn relative_to(self, dir: &Path) -> EnvFile {
+        match self {
+            EnvFile::Path(path) => EnvFile::Path(dir.join(path).display().to_string()),
+            EnvFile::Detailed(details) => EnvFile::Detailed(EnvFileDetails {
+                path: dir.join(details.path).display().to_string(),
+                optional: details.optional,
+            }),
+        }
+    }
+
+    /// Read the file's variables, in order, resolving a relative path against
+    /// `base_dir`. A missing optional file has none.
+    pub fn load(&self, base_dir: &Path) -> Result<Vec<(String, String)>, TaskRunnerError> {
+        let path = base_dir.join(self.path());
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && self.is_optional() => {
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(TaskRunnerError::EnvFileError(format!("{}: {}", path.display(), e))),
+        };
+        parse(&content).map_err(|e| TaskRunnerError::EnvFileError(format!("{}: {}", path.display(), e)))
+    }
+}
+
+impl std::fmt::Display for EnvFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_optional() {
+            write!(f, "{} (optional)", self.path())
+        } else {
+            write!(f, "{}", self.path())
+        }
+    }
+}
+
+/// Parse dotenv content: `KEY=value` lines, optionally starting with `export`.
+///
+/// Blank lines and lines starting with `#` are ignored. Single-quoted values are
+/// taken literally, double-quoted values may span lines and understand `\n`,
+/// `\t`, `\"` and `\\`, and unquoted values end at a `#` comment following a
+/// space or a tab. Values are not expanded.
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    let mut lines = content.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = index + 1;
+
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=value", line_number))?;
+        let key = key.trim();
+        let valid_key = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_key {
+            return Err(format!("line {}: '{}' is not a valid variable name", line_number, key));
+        }
+
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some('\'') => {
+                let end = value[1..].find('\'')
+                    .ok_or_else(|| format!("line {}: unterminated single quote", line_number))?;
+                value[1..end + 1].to_string()
+            }
+            Some('"') => {
+                // The value continues on the following lines until the closing quote
+                let mut raw = value[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&raw) {
+                        break end;
+                    }
+                    let (_, next) = lines.next()
+                        .ok_or_else(|| format!("line {}: unterminated double quote", line_number))?;
+                    raw.push('\n');
+                    raw.push_str(next);
+                };
+                unescape(&raw[..end])
+            }
+            _ => {
+                // A comment starts at a `#` after a space or a tab
+                let comment = value.match_indices('#')
+                    .map(|(i, _)| i)
+                    .find(|&i| value[..i].ends_with(char::is_whitespace));
+                value[..comment.unwrap_or(value.len())].trim_end().to_string()
+            }
+        };
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Position of the first `"` not escaped by a backslash
+fn closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(c @ ('"' | '\\' | '$')) => result.push(c),
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Where the value of an environment variable comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvSource {
+    /// The environment of the runner
+    Process,
+    /// A file of the configuration's `env_files`
+    ConfigFile(String),
+    /// The configuration's `env`
+    Config,
+    /// A file of the task's `env_files`
+    TaskFile(String),
+    /// The task's `env`
+    Task,
+}
+
+impl EnvSource {
+    /// Whether the value is declared by the configuration rather than inherited
+    pub fn is_declared(&self) -> bool {
+        !matches!(self, EnvSource::Process)
+    }
+}
+
+impl std::fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvSource::Process => write!(f, "process environment"),
+            EnvSource::ConfigFile(path) | EnvSource::TaskFile(path) => write!(f, "{}", path),
+            EnvSource::Config => write!(f, "config env"),
+            EnvSource::Task => write!(f, "task env"),
+        }
+    }
+}
+
+/// The environment of the configuration, without the task level: the process
+/// environment, the configuration's `env_files` in order, and its `env`
+pub fn resolve_config(config: &Config) -> Result<HashMap<String, (String, EnvSource)>, TaskRunnerError> {
+    let mut env: HashMap<String, (String, EnvSource)> = std::env::vars()
+        .map(|(key, value)| (key, (value, EnvSource::Process)))
+        .collect();
+
+    for file in &config.env_files {
+        for (key, value) in file.load(&config.dir)? {
+            env.insert(key, (value, EnvSource::ConfigFile(file.path().to_string())));
+        }
+    }
+    for (key, value) in &config.env {
+        env.insert(key.clone(), (value.clone(), EnvSource::Config));
+    }
+    Ok(env)
+}
+
+/// The environment a task's commands run with, with the source of each variable.
+///
+/// Later sources override earlier ones: the process environment, the
+/// configuration's `env_files` in order, its `env`, the task's `env_files`
+/// in order, and the task's `env`.
+pub fn resolve(config: &Config, task: &Task) -> Result<HashMap<String, (String, EnvSource)>, TaskRunnerError> {
+    let mut env = resolve_config(config)?;
+    for file in &task.env_files {
+        for (key, value) in file.load(&task.base_dir)? {
+            env.insert(key, (value, EnvSource::TaskFile(file.path().to_string())));
+        }
+    }
+    for (key, value) in &task.env {
+        env.insert(key.clone(), (value.clone(), EnvSource::Task));
+    }
+
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+# Database settings
+DATABASE_URL=postgres://localhost/dev
+export API_KEY = abc123   # inline comment
+EMPTY=
+SINGLE='literal $HOME \n'
+DOUBLE="line one\nline \"two\""
+MULTI="first
+second"
+HASH=a#b
+"#;
+        let vars: HashMap<_, _> = parse(content).unwrap().into_iter().collect();
+        assert_eq!(vars["DATABASE_URL"], "postgres://localhost/dev");
+        assert_eq!(vars["API_KEY"], "abc123");
+        assert_eq!(vars["EMPTY"], "");
+        assert_eq!(vars["SINGLE"], "literal $HOME \\n");
+        assert_eq!(vars["DOUBLE"], "line one\nline \"two\"");
+        assert_eq!(vars["MULTI"], "first\nsecond");
+        assert_eq!(vars["HASH"], "a#b");
+        assert_eq!(parse("TABBED=value\t# note after a tab").unwrap(), [("TABBED".to_string(), "value".to_string())]);
+
+        assert!(parse("NO_EQUALS").is_err());
+        assert!(parse("1KEY=x").is_err());
+        assert!(parse("KEY=\"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let dir = std::env::temp_dir().join(format!("task-runner-dotenv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shared = dir.join("shared.env");
+        let task_file = dir.join("task.env");
+        std::fs::write(&shared, "A=shared\nB=shared\nC=shared\n").unwrap();
+        std::fs::write(&task_file, "B=task-file\nC=task-file\n").unwrap();
+
+        let mut config: Config = serde_yaml::from_str("tasks: {}").unwrap();
+        config.env_files = vec![
+            EnvFile::Path(shared.display().to_string()),
+            EnvFile::Detailed(EnvFileDetails {
+                path: dir.join("missing.env").display().to_string(),
+                optional: true,
+            }),
+        ];
+        config.env.insert("B".to_string(), "config".to_string());
+        let mut task = Task::new(vec!["true".to_string()]);
+        task.env_files = vec![EnvFile::Path(task_file.display().to_string())];
+        task.env.insert("C".to_string(), "task".to_string());
+
+        let env = resolve(&config, &task).unwrap();
+        assert_eq!(env["A"], ("shared".to_string(), EnvSource::ConfigFile(shared.display().to_string())));
+        assert_eq!(env["B"], ("task-file".to_string(), EnvSource::TaskFile(task_file.display().to_string())));
+        assert_eq!(env["C"], ("task".to_string(), EnvSource::Task));
+
+        task.env_files.push(EnvFile::Path(dir.join("missing.env").display().to_string()));
+        assert!(resolve(&config, &task).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
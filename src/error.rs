@@ -17,12 +17,48 @@ pub enum TaskRunnerError {
     #[error("Task execution failed: {0}")]
     TaskExecutionFailed(String),
     
+    #[error("Command cancelled: {0}")]
+    Cancelled(String),
+    
+    #[error("Interrupted: {0}")]
+    Interrupted(String),
+    
+    #[error("Run timed out after {0} seconds")]
+    RunTimeout(u64),
+    
     #[error("No tasks specified")]
     NoTasksSpecified,
     
     #[error("Dependency '{0}' not found for task '{1}'")]
     DependencyNotFound(String, String),
     
+    #[error("Task '{1}' skipped because dependency '{0}' failed")]
+    DependencyFailed(String, String),
+    
+    #[error("Task '{0}' not started because the run stopped")]
+    NotStarted(String),
+    
+    #[error("Lock '{0}' is not registered for this run")]
+    UnknownLock(String),
+    
+    #[error("Cache error: {0}")]
+    CacheError(String),
+    
+    #[error("Watch error: {0}")]
+    WatchError(String),
+    
+    #[error("Parameter error: {0}")]
+    ParamError(String),
+    
+    #[error("Template error: {0}")]
+    TemplateError(String),
+    
+    #[error("Environment variable error: {0}")]
+    EnvVarError(String),
+    
+    #[error("Env file error: {0}")]
+    EnvFileError(String),
+    
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     
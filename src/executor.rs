@@ -1,21 +1,154 @@
+use crate::cache::{self, CapturedLog, HttpCache, LocalCache, TaskCache};
+use crate::output::{self, OutputSink, OutputTaps, PrefixedOutput, ServiceState, StatusLine};
 use crate::config::Config;
+use crate::dotenv;
 use crate::error::TaskRunnerError;
-use crate::task::{ExecutionMode, Task};
-use crate::utils::expand_env_vars;
+use crate::fingerprint::{self, FingerprintInputs, StateStore};
+use crate::params::{self, ParamArgs};
+use crate::process::{self, InterruptHandle, StopSignal, DEFAULT_KILL_GRACE_PERIOD};
+use crate::resources::{JobSlots, ResourceLocks};
+use crate::service::{self, NotReady, ReadinessProbe, Restart, RunningService, ServiceLauncher, Services};
+use crate::shell::Shell;
+use crate::task::{ExecutionMode, RestartPolicy, RetryBackoff, RetryPolicy, Task, TaskCommand};
+use crate::utils::{expand_declared_env_vars, expand_env_vars, format_duration, join_path};
+use crate::vars::RunVars;
 use colored::*;
+use futures::stream::{FuturesUnordered, StreamExt};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::collections::HashMap;
-use std::process::{Command, Stdio};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
+use tokio::process::Command;
+use tokio::task::JoinSet;
 use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
 
 pub struct TaskExecutor {
     config: Config,
+    interrupt: InterruptHandle,
+}
+
+/// Options for a single run of one or more tasks
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// How tasks are scheduled
+    pub execution_mode: ExecutionMode,
+    
+    /// Keep running independent tasks after a failure
+    pub continue_on_error: bool,
+    
+    /// Time limit for the whole run (in seconds)
+    pub run_timeout: Option<u64>,
+    
+    /// Maximum number of commands running at once (overrides `max_parallel`)
+    pub jobs: Option<usize>,
+    
+    /// Run tasks even when their sources are unchanged
+    pub force: bool,
+    
+    /// Prefix every line of output with the name of its task
+    pub prefix_output: bool,
+    
+    /// Keep services running until they exit or the run is interrupted
+    pub keep_services: bool,
+    
+    /// Parameter values given on the command line
+    pub params: ParamArgs,
+    
+    /// Arguments appended to the last command of each task named on the command line
+    pub extra_args: Vec<String>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            execution_mode: ExecutionMode::Auto,
+            continue_on_error: false,
+            run_timeout: None,
+            jobs: None,
+            force: false,
+            prefix_output: false,
+            keep_services: false,
+            params: ParamArgs::default(),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+/// State shared by every task of a single run
+#[derive(Clone)]
+struct RunContext {
+    config: Arc<Config>,
+    cancel: CancellationToken,
+    interrupt: InterruptHandle,
+    slots: JobSlots,
+    locks: ResourceLocks,
+    state: StateStore,
+    cache: Arc<TaskCache>,
+    services: Services,
+    /// Set when output lines are prefixed with their task's name
+    output: Option<Arc<PrefixedOutput>>,
+    /// Set when services are summarized below the other bars
+    status: Option<Arc<StatusLine>>,
+    force: bool,
+}
+
+/// Settings shared by every command of a task
+struct CommandContext {
+    shell: Shell,
+    env_vars: HashMap<String, String>,
+    working_dir: Option<String>,
+    timeout: Option<u64>,
+    kill_grace_period: Duration,
+    interrupt: InterruptHandle,
+    slots: JobSlots,
+    weight: u32,
+    /// Where command output is shown
+    sink: OutputSink,
+    /// Where command output is captured, for tasks that are cached
+    log: Option<Arc<Mutex<CapturedLog>>>,
+}
+
+/// Details of a task that completed successfully
+#[derive(Debug, Clone, Default)]
+struct TaskReport {
+    /// Most attempts any single command needed
+    attempts: u32,
+    
+    /// The task was skipped because its sources had not changed
+    up_to_date: bool,
+    
+    /// The task's outputs were restored from the cache instead of running it
+    cached: bool,
+    
+    /// The task is a service that became ready and keeps running
+    service: bool,
+    
+    /// Times the service, or the commands of the task, were restarted after exiting
+    restarts: u32,
+}
+
+type TaskResult = Result<TaskReport, TaskRunnerError>;
+
+/// Why a running command was stopped before it exited on its own
+enum Interrupt {
+    Timeout,
+    Cancelled,
 }
 
 impl TaskExecutor {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config,
+            interrupt: InterruptHandle::new(),
+        }
+    }
+    
+    /// Handle used to interrupt every in-flight command (e.g. on Ctrl-C)
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
     }
     
     /// List all available tasks
@@ -35,7 +168,17 @@ impl TaskExecutor {
                 if details {
                     self.print_task_details(&task_name, task);
                 } else {
-                    println!("  {} {}", "•".green(), task_name.bold());
+                    let origin = if task.from_user_config {
+                        " (user)"
+                    } else if task.inherited_from.is_some() {
+                        " (inherited)"
+                    } else if self.config.local_overrides.as_ref()
+                        .is_some_and(|overrides| overrides.defined_tasks.contains(&task_name)) {
+                        " (local)"
+                    } else {
+                        ""
+                    };
+                    println!("  {} {}{}", "•".green(), task_name.bold(), origin.dimmed());
                     if let Some(desc) = &task.description {
                         println!("    {}", desc.dimmed());
                     }
@@ -53,6 +196,16 @@ impl TaskExecutor {
             .ok_or_else(|| TaskRunnerError::TaskNotFound(task_name.to_string()))?;
         
         println!("{} Task: {}", "📋".blue(), task_name.bold());
+        if let Some(environment) = &self.config.environment {
+            println!("   Environment profile: {}", environment);
+        }
+        if let Some(overrides) = &self.config.local_overrides {
+            if overrides.fields.is_empty() {
+                println!("   Local overrides: {}", overrides.path.display());
+            } else {
+                println!("   Local overrides: {} ({})", overrides.path.display(), overrides.fields.join(", "));
+            }
+        }
         println!();
         
         self.print_task_details(task_name, task);
@@ -60,13 +213,86 @@ impl TaskExecutor {
         Ok(())
     }
     
-    /// Run tasks with specified execution mode
+    /// Print the environment a task's commands run with and the source of each
+    /// value, leaving out inherited process variables unless `all` is set
+    pub async fn show_task_env(
+        &self,
+        task_name: &str,
+        params: &HashMap<String, String>,
+        all: bool,
+    ) -> Result<(), TaskRunnerError> {
+        let mut task = self.config.get_task(task_name)
+            .ok_or_else(|| TaskRunnerError::TaskNotFound(task_name.to_string()))?
+            .clone();
+        
+        // Show env values the way commands see them, with templates rendered
+        let values = params::resolve(task_name, &task, params)?;
+        RunVars::new(&self.config).render_task(task_name, &mut task, &values).await?;
+        
+        let env = dotenv::resolve(&self.config, &task)?;
+        let mut keys: Vec<&String> = env.iter()
+            .filter(|(_, (_, source))| all || source.is_declared())
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        
+        println!("{} Environment of {}", "📋".blue(), task_name.bold());
+        println!();
+        for key in keys {
+            let (value, source) = &env[key];
+            println!("  {}={} {}", key, value, format!("({})", source).dimmed());
+        }
+        
+        Ok(())
+    }
+    
+    /// Run tasks with the given options
     pub async fn run_tasks(
         &self,
         task_names: &[String],
-        execution_mode: ExecutionMode,
-        continue_on_error: bool,
+        options: &RunOptions,
     ) -> Result<(), TaskRunnerError> {
+        let (results, run_timed_out) = self.execute_run(task_names, options).await?;
+        
+        // Print results
+        self.print_execution_results(&results);
+        
+        if let Some(signal) = self.interrupt.signal() {
+            return Err(TaskRunnerError::Interrupted(format!("received {}", signal)));
+        }
+        
+        if run_timed_out {
+            return Err(TaskRunnerError::RunTimeout(options.run_timeout.unwrap_or_default()));
+        }
+        
+        // Check if any tasks failed, naming those that didn't just stop because of another
+        if results.values().any(|result| result.is_err()) {
+            let mut failed_tasks: Vec<_> = results.iter()
+                .filter(|(_, result)| matches!(result, Err(e) if !matches!(e,
+                    TaskRunnerError::DependencyFailed(..)
+                        | TaskRunnerError::NotStarted(_)
+                        | TaskRunnerError::Cancelled(_)
+                )))
+                .map(|(name, _)| name)
+                .collect();
+            failed_tasks.sort();
+            return Err(TaskRunnerError::TaskExecutionFailed(
+                format!("{} tasks failed: {:?}", failed_tasks.len(), failed_tasks)
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Run tasks and return the result of every task of the run, along with
+    /// whether the run timeout expired
+    async fn execute_run(
+        &self,
+        task_names: &[String],
+        options: &RunOptions,
+    ) -> Result<(HashMap<String, TaskResult>, bool), TaskRunnerError> {
+        let continue_on_error = options.continue_on_error;
+        
         // Get execution order including dependencies
         let execution_order = self.config.get_execution_order(task_names)?;
         
@@ -74,6 +300,8 @@ impl TaskExecutor {
             return Err(TaskRunnerError::NoTasksSpecified);
         }
         
+        let config = self.config_for_run(task_names, &execution_order, options).await?;
+        
         println!("{} Executing {} tasks...", "🚀".green(), execution_order.len());
         println!();
         
@@ -82,141 +310,549 @@ impl TaskExecutor {
             .template("{spinner:.green} {wide_msg}")
             .unwrap();
         
-        let mut handles = Vec::new();
         let mut results = HashMap::new();
         
-        match execution_mode {
+        let run = RunContext {
+            config: Arc::new(config),
+            // Cancelled on Ctrl-C through the interrupt handle, by the run timeout, or by fail-fast
+            cancel: self.interrupt.stop_token().child_token(),
+            interrupt: self.interrupt.clone(),
+            // Job slots shared by every command: --jobs, then max_parallel, then CPU count
+            slots: JobSlots::configured(options.jobs, self.config.max_parallel),
+            locks: ResourceLocks::new(
+                execution_order.iter()
+                    .filter_map(|name| self.config.get_task(name))
+                    .flat_map(|task| &task.locks),
+                &self.config.locks,
+            ),
+            state: StateStore::new(self.config.dir.join(fingerprint::STATE_DIR)),
+            cache: Arc::new(self.task_cache()),
+            services: Services::default(),
+            output: options.prefix_output.then(|| {
+                Arc::new(PrefixedOutput::new(multi_progress.clone(), &execution_order))
+            }),
+            status: execution_order.iter()
+                .any(|name| self.config.get_task(name).is_some_and(|task| task.service))
+                .then(|| Arc::new(StatusLine::new(&multi_progress))),
+            force: options.force,
+        };
+        
+        // Stop everything that is still running once the run timeout expires
+        let watchdog = options.run_timeout.map(|timeout_secs| {
+            let run_token = run.cancel.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_secs(timeout_secs)).await;
+                run_token.cancel();
+            })
+        });
+        
+        match options.execution_mode {
             ExecutionMode::Parallel => {
-                // Run all tasks in parallel
-                for task_name in &execution_order {
-                    let pb = multi_progress.add(ProgressBar::new_spinner());
-                    pb.set_style(style.clone());
-                    pb.set_message(format!("Running {}", task_name));
-                    
-                    let config = self.config.clone();
-                    let task_name = task_name.clone();
-                    
-                    let handle = tokio::spawn(async move {
-                        let result = Self::execute_single_task(&config, &task_name, &pb).await;
-                        (task_name, result)
-                    });
-                    
-                    handles.push(handle);
-                }
-                
-                // Wait for all tasks to complete
-                for handle in handles {
-                    let (task_name, result) = handle.await.unwrap();
-                    results.insert(task_name, result);
-                }
+                // Schedule tasks as soon as their dependencies have succeeded
+                results = self.run_dependency_graph(
+                    &run,
+                    &execution_order,
+                    &multi_progress,
+                    &style,
+                    continue_on_error,
+                ).await;
             }
-            ExecutionMode::Sequential => {
-                // Run tasks sequentially
+            ExecutionMode::Sequential | ExecutionMode::Auto => {
+                // Run tasks in order; in sequential mode the commands within each task
+                // run sequentially too, otherwise each task's own command mode applies
                 for task_name in &execution_order {
-                    let pb = multi_progress.add(ProgressBar::new_spinner());
-                    pb.set_style(style.clone());
-                    pb.set_message(format!("Running {}", task_name));
-                    
-                    let result = Self::execute_single_task(&self.config, task_name, &pb).await;
-                    let is_err = result.is_err();
-                    results.insert(task_name.clone(), result);
+                    if run.cancel.is_cancelled() {
+                        break;
+                    }
                     
-                    if is_err {
-                        if !continue_on_error {
-                            break;
-                        }
+                    if let Some(dep) = Self::failed_dependency(&self.config, task_name, &results) {
+                        results.insert(
+                            task_name.clone(),
+                            Err(TaskRunnerError::DependencyFailed(dep, task_name.clone())),
+                        );
+                        continue;
                     }
-                }
-            }
-            ExecutionMode::Auto => {
-                // Determine execution mode based on task configuration
-                for task_name in &execution_order {
-                    let task = self.config.get_task(task_name).unwrap();
-                    let _task_mode = task.execution_mode();
                     
-                    let pb = multi_progress.add(ProgressBar::new_spinner());
-                    pb.set_style(style.clone());
-                    pb.set_message(format!("Running {}", task_name));
+                    let task_mode = match options.execution_mode {
+                        ExecutionMode::Auto => self.config.get_task(task_name).unwrap().execution_mode(),
+                        mode => mode,
+                    };
+                    let pb = Self::task_spinner(&run, &multi_progress, &style, task_name);
                     
-                    let result = Self::execute_single_task(&self.config, task_name, &pb).await;
+                    let result = Self::execute_single_task(
+                        &run, task_name, task_mode, &pb
+                    ).await;
                     let is_err = result.is_err();
                     results.insert(task_name.clone(), result);
                     
-                    if is_err {
-                        if !continue_on_error {
-                            break;
-                        }
+                    if is_err && !continue_on_error {
+                        break;
                     }
                 }
             }
         }
         
+        // Tasks the run stopped before starting are skipped
+        Self::skip_unstarted(&self.config, &execution_order, &mut results);
+        
+        // A run of nothing but services keeps them up until they exit or the run is stopped
+        let only_services = task_names.iter()
+            .all(|name| self.config.get_task(name).is_some_and(|task| task.service));
+        let keep_services = options.keep_services
+            || (only_services && results.values().all(|result| result.is_ok()));
+        if keep_services && !run.cancel.is_cancelled() && !run.services.is_empty().await {
+            let message = format!("{} Services are running, press Ctrl-C to stop", "●".green());
+            match &run.output {
+                Some(output) => output.message(&message),
+                None => println!("{}", message),
+            }
+            
+            run.services.wait(&run.cancel).await;
+        }
+        
+        if let Some(status) = &run.status {
+            status.finish();
+        }
         multi_progress.clear().unwrap();
         
-        // Print results
-        self.print_execution_results(&results);
+        // Services never outlive the run; forward the runner's own signal if it was interrupted
+        let outcomes = run.services.stop_all(
+            self.interrupt.signal().unwrap_or(StopSignal::Terminate),
+        ).await;
         
-        // Check if any tasks failed
-        let failed_tasks: Vec<_> = results.iter()
-            .filter(|(_, result)| result.is_err())
-            .map(|(name, _)| name)
-            .collect();
+        // Services that crashed for good fail like any other task
+        for (name, outcome) in outcomes {
+            match outcome.exited {
+                Some(status) if !status.success() => {
+                    results.insert(name.clone(), Err(TaskRunnerError::TaskExecutionFailed(
+                        format!("Service '{}' crashed: {}", name, status)
+                    )));
+                }
+                _ => {
+                    if let Some(Ok(report)) = results.get_mut(&name) {
+                        report.restarts = outcome.restarts;
+                    }
+                }
+            }
+        }
         
-        if !failed_tasks.is_empty() {
-            return Err(TaskRunnerError::TaskExecutionFailed(
-                format!("{} tasks failed: {:?}", failed_tasks.len(), failed_tasks)
+        // The watchdog only finishes on its own when the run timeout has expired
+        let run_timed_out = watchdog.is_some_and(|watchdog| {
+            let expired = watchdog.is_finished();
+            watchdog.abort();
+            expired
+        });
+        
+        Ok((results, run_timed_out))
+    }
+    
+    /// The configuration with the templates of the tasks of the run rendered, and
+    /// extra arguments appended to the tasks named on the command line
+    async fn config_for_run(
+        &self,
+        task_names: &[String],
+        execution_order: &[String],
+        options: &RunOptions,
+    ) -> Result<Config, TaskRunnerError> {
+        if let Some(key) = options.params.shared.keys().find(|key| {
+            !task_names.iter().any(|name| {
+                self.config.get_task(name).is_some_and(|task| task.params.contains_key(*key))
+            })
+        }) {
+            return Err(TaskRunnerError::ParamError(
+                format!("None of the tasks to run has a parameter '{}'", key)
             ));
         }
         
-        Ok(())
+        let mut config = self.config.clone();
+        let mut vars = RunVars::new(&self.config);
+        for task_name in execution_order {
+            let shell = Shell::resolve(
+                config.tasks[task_name].shell.as_deref(),
+                config.shell.as_deref(),
+            ).map_err(TaskRunnerError::ConfigParseError)?;
+            let task = config.tasks.get_mut(task_name).unwrap();
+            let named = task_names.contains(task_name);
+            
+            // Dependencies only get their default values
+            let mut given = HashMap::new();
+            if named {
+                given.extend(options.params.shared.iter()
+                    .filter(|(key, _)| task.params.contains_key(*key))
+                    .map(|(key, value)| (key.clone(), value.clone())));
+                if let Some(values) = options.params.per_task.get(task_name) {
+                    given.extend(values.clone());
+                }
+            }
+            let values = params::resolve(task_name, task, &given)?;
+            vars.render_task(task_name, task, &values).await?;
+            
+            if named && !options.extra_args.is_empty() {
+                let Some(last) = task.commands.last_mut() else {
+                    return Err(TaskRunnerError::ParamError(
+                        format!("Task '{}' has no commands to pass arguments to", task_name)
+                    ));
+                };
+                let args: Vec<String> = options.extra_args.iter()
+                    .map(|arg| shell.quote(arg))
+                    .collect();
+                last.set_run(format!("{} {}", last.run(), args.join(" ")));
+            }
+        }
+        
+        Ok(config)
     }
     
-    /// Execute a single task
-    async fn execute_single_task(
+    /// Run tasks as a dependency graph, starting each task once all of its
+    /// dependencies have succeeded and running independent branches concurrently.
+    ///
+    /// Tasks downstream of a failure are skipped. Unless `continue_on_error` is
+    /// set, the first failure cancels the run, which stops tasks that are still
+    /// running and prevents new ones from starting.
+    async fn run_dependency_graph(
+        &self,
+        run: &RunContext,
+        execution_order: &[String],
+        multi_progress: &MultiProgress,
+        style: &ProgressStyle,
+        continue_on_error: bool,
+    ) -> HashMap<String, TaskResult> {
+        let mut results = HashMap::new();
+        let mut pending: Vec<String> = execution_order.to_vec();
+        let mut running = JoinSet::new();
+        
+        loop {
+            if !run.cancel.is_cancelled() {
+                // `pending` is in topological order, so skips cascade in a single pass
+                let mut i = 0;
+                while i < pending.len() {
+                    let task_name = &pending[i];
+                    
+                    if let Some(dep) = Self::failed_dependency(&self.config, task_name, &results) {
+                        let task_name = pending.remove(i);
+                        results.insert(
+                            task_name.clone(),
+                            Err(TaskRunnerError::DependencyFailed(dep, task_name)),
+                        );
+                        continue;
+                    }
+                    
+                    let task = self.config.get_task(task_name).unwrap();
+                    let ready = task.dependencies.iter()
+                        .all(|dep| matches!(results.get(dep), Some(Ok(_))));
+                    
+                    if !ready {
+                        i += 1;
+                        continue;
+                    }
+                    
+                    let task_name = pending.remove(i);
+                    let pb = Self::task_spinner(run, multi_progress, style, &task_name);
+                    
+                    let command_mode = task.execution_mode();
+                    let run = run.clone();
+                    running.spawn(async move {
+                        let result = Self::execute_single_task(
+                            &run, &task_name, command_mode, &pb
+                        ).await;
+                        (task_name, result)
+                    });
+                }
+            }
+            
+            // Wait for the next running task to finish
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (task_name, result) = joined.expect("task execution panicked");
+            
+            if result.is_err() && !continue_on_error {
+                run.cancel.cancel();
+            }
+            results.insert(task_name, result);
+        }
+        
+        results
+    }
+    
+    /// Mark the tasks of `execution_order` without a result as skipped: those
+    /// downstream of a failure because of it, the others because the run stopped
+    fn skip_unstarted(
+        config: &Config,
+        execution_order: &[String],
+        results: &mut HashMap<String, TaskResult>,
+    ) {
+        // `execution_order` is topological, so a task's dependencies are settled before it
+        for task_name in execution_order {
+            if results.contains_key(task_name) {
+                continue;
+            }
+            let failed_dependency = config.get_task(task_name).and_then(|task| {
+                task.dependencies.iter().find(|dep| matches!(results.get(*dep), Some(Err(e)) if !matches!(e,
+                    TaskRunnerError::NotStarted(_)
+                        | TaskRunnerError::Cancelled(_)
+                        | TaskRunnerError::Interrupted(_)
+                )))
+            });
+            let skipped = match failed_dependency {
+                Some(dep) => TaskRunnerError::DependencyFailed(dep.clone(), task_name.clone()),
+                None => TaskRunnerError::NotStarted(task_name.clone()),
+            };
+            results.insert(task_name.clone(), Err(skipped));
+        }
+    }
+    
+    /// Return the first dependency of a task that failed or was skipped
+    fn failed_dependency(
         config: &Config,
         task_name: &str,
+        results: &HashMap<String, TaskResult>,
+    ) -> Option<String> {
+        let task = config.get_task(task_name)?;
+        task.dependencies.iter()
+            .find(|dep| matches!(results.get(*dep), Some(Err(_))))
+            .cloned()
+    }
+    
+    /// Execute a single task, running its commands according to `command_mode`
+    async fn execute_single_task(
+        run: &RunContext,
+        task_name: &str,
+        command_mode: ExecutionMode,
         pb: &ProgressBar,
-    ) -> Result<(), TaskRunnerError> {
+    ) -> TaskResult {
+        let config = &run.config;
+        let cancel = &run.cancel;
         let task = config.get_task(task_name)
             .ok_or_else(|| TaskRunnerError::TaskNotFound(task_name.to_string()))?;
         
-        let start_time = Instant::now();
-        
-        // Set up environment variables
-        let mut env_vars: HashMap<String, String> = std::env::vars().collect();
-        env_vars.extend(config.env.clone());
-        env_vars.extend(task.env.clone());
+        // Set up environment variables: the process env, then env files and env of the config and the task
+        let resolved_env = dotenv::resolve(config, task)
+            .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+        let declared_env: HashMap<String, String> = resolved_env.iter()
+            .filter(|(_, (_, source))| source.is_declared())
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect();
+        let env_vars: HashMap<String, String> = resolved_env.into_iter()
+            .map(|(key, (value, _))| (key, value))
+            .collect();
         
-        // Determine working directory: task-specific or default from config
-        // Expand environment variables in working directory path
-        let working_dir = task.working_dir.as_deref()
-            .or_else(|| config.default_working_dir.as_deref())
-            .map(|dir| expand_env_vars(dir, &env_vars));
-        let working_dir = working_dir.as_deref();
+        // Determine working directory: task-specific or default from config,
+        // relative to the configuration file that defines it
+        let working_dir = config.working_dir(task, &env_vars)
+            .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
         
         // Determine timeout: task-specific or default from config
         let timeout = task.timeout
             .or(config.default_timeout);
         
-        // Execute commands
+        // Determine shell: task-specific, global from config, or the platform shell
+        let shell = Shell::resolve(task.shell.as_deref(), config.shell.as_deref())
+            .map_err(TaskRunnerError::ConfigParseError)
+            .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+        
+        let context = CommandContext {
+            shell,
+            env_vars,
+            working_dir,
+            timeout,
+            kill_grace_period: Duration::from_secs(
+                task.kill_grace_period
+                    .or(config.kill_grace_period)
+                    .unwrap_or(DEFAULT_KILL_GRACE_PERIOD)
+            ),
+            interrupt: run.interrupt.clone(),
+            slots: run.slots.clone(),
+            weight: task.weight.unwrap_or(1),
+            sink: match &run.output {
+                Some(output) => OutputSink::Prefixed {
+                    output: output.clone(),
+                    task_name: task_name.to_string(),
+                },
+                None => OutputSink::Terminal,
+            },
+            log: task.cache.then(Default::default),
+        };
+        
+        // Expand every command up front, so that a missing variable stops the task before anything runs.
+        // A shell expands the variables the configuration doesn't declare itself.
+        let expanded_commands = task.commands.iter()
+            .map(|command| match context.shell {
+                Shell::Exec => expand_env_vars(command.run(), &context.env_vars),
+                Shell::Interpreter { .. } => {
+                    expand_declared_env_vars(command.run(), &declared_env, &context.env_vars)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+        
+        // Skip the task when its sources and commands are unchanged since its last successful run
+        let base_dir = PathBuf::from(context.working_dir.as_deref().unwrap_or("."));
+        let fingerprint = if task.sources.is_empty() {
+            None
+        } else {
+            let inputs = FingerprintInputs {
+                commands: expanded_commands.clone(),
+                env: fingerprint::sorted_env(&declared_env),
+                working_dir: context.working_dir.as_deref(),
+                shell: context.shell.to_string(),
+                source_patterns: &task.sources,
+            };
+            Some(fingerprint::compute(&base_dir, &inputs)
+                .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?)
+        };
+        
+        if let Some(fingerprint) = &fingerprint {
+            let unchanged = run.state.load(task_name).as_deref() == Some(fingerprint.as_str());
+            let up_to_date = !run.force && unchanged && fingerprint::outputs_exist(&base_dir, &task.outputs)
+                .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+            if up_to_date {
+                pb.finish_with_message(format!("{} {} is up to date", "≡".blue(), task_name));
+                return Ok(TaskReport { up_to_date: true, ..Default::default() });
+            }
+        }
+        
+        // Cached tasks are looked up by their fingerprint, tool versions and outputs
+        let cache_key = match &fingerprint {
+            Some(fingerprint) if task.cache => {
+                let mut tool_versions = Vec::new();
+                for command in &task.tool_versions {
+                    let version = Self::tool_version(command, &context).await
+                        .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+                    tool_versions.push(version);
+                }
+                Some(cache::cache_key(fingerprint, &tool_versions, &task.outputs))
+            }
+            _ => None,
+        };
+        
+        if let (Some(key), Some(fingerprint)) = (&cache_key, &fingerprint) {
+            if !run.force {
+                match Self::restore_from_cache(&run.cache, key, &base_dir, pb).await {
+                    Ok(true) => {
+                        run.state.save(task_name, fingerprint)?;
+                        pb.finish_with_message(format!("{} {} restored from cache", "✓".green(), task_name));
+                        return Ok(TaskReport { cached: true, ..Default::default() });
+                    }
+                    Ok(false) => {}
+                    Err(e) => Self::report(pb, format!("  {} {} cache restore failed, running: {}",
+                        "⚠".yellow(), task_name, e)),
+                }
+            }
+        }
+        
+        // Hold the task's locks for its whole run, taking them in a fixed order to avoid deadlocks
+        let mut lock_permits = Vec::new();
+        for lock in ResourceLocks::order(&task.locks) {
+            let free = run.locks.try_acquire(lock)
+                .inspect_err(|e| pb.finish_with_message(format!("{} {}", "✗".red(), e)))?;
+            let permit = match free {
+                Some(permit) => permit,
+                None => {
+                    pb.set_message(format!("{} blocked on {}", task_name, lock));
+                    tokio::select! {
+                        permit = run.locks.acquire(lock) => permit?,
+                        _ = cancel.cancelled() => {
+                            pb.finish_with_message(format!("{} {} cancelled while blocked on {}",
+                                "⊘".yellow(), task_name, lock));
+                            return Err(if run.interrupt.is_interrupted() {
+                                TaskRunnerError::Interrupted(format!("Task '{}'", task_name))
+                            } else {
+                                TaskRunnerError::Cancelled(format!("Task '{}'", task_name))
+                            });
+                        }
+                    }
+                }
+            };
+            lock_permits.push(permit);
+        }
+        pb.set_message(format!("Running {}", task_name));
+        
+        if task.service {
+            let command = expanded_commands[0].clone();
+            return Self::start_service(run, task_name, task, command, &context, lock_permits, pb).await;
+        }
+        
+        let start_time = Instant::now();
+        
+        // Execute commands, keeping the number of attempts each one took
         let mut command_results = Vec::new();
+        let mut restart_count = 0;
         
-        for (i, command) in task.commands.iter().enumerate() {
-            // Expand environment variables in command string
-            let expanded_command = expand_env_vars(command, &env_vars);
-            pb.set_message(format!("{} [{}] {}", task_name, i + 1, &expanded_command));
+        if command_mode == ExecutionMode::Parallel {
+            // Sibling commands are cancelled when one fails, unless the task continues on error
+            let task_token = cancel.child_token();
             
-            let result = Self::execute_command(
-                &expanded_command, 
-                &env_vars, 
-                working_dir,
-                timeout
-            ).await;
-            let is_err = result.is_err();
-            command_results.push(result);
+            // Launch every command at once and report each as it finishes
+            let mut running: FuturesUnordered<_> = task.commands.iter()
+                .enumerate()
+                .map(|(i, command)| {
+                    let expanded_command = expanded_commands[i].clone();
+                    let label = format!("{} [{}]", task_name, i + 1);
+                    let context = &context;
+                    let task_token = &task_token;
+                    async move {
+                        let (attempts, restarts, result) = Self::supervise_command(
+                            &label,
+                            &expanded_command,
+                            command,
+                            task,
+                            context,
+                            task_token,
+                            pb,
+                        ).await;
+                        (label, expanded_command, attempts, restarts, result)
+                    }
+                })
+                .collect();
+            
+            pb.set_message(format!("{} [{} commands in parallel]", task_name, running.len()));
             
-            if is_err {
-                if !task.continue_on_error {
+            while let Some((label, expanded_command, attempts, restarts, result)) = running.next().await {
+                match &result {
+                    Ok(()) => Self::report(pb, format!("  {} {} {}",
+                        "✓".green(), label, expanded_command)),
+                    Err(e) => Self::report(pb, format!("  {} {} {}: {}",
+                        "✗".red(), label, expanded_command, e)),
+                }
+                
+                if result.is_err() && !task.continue_on_error {
+                    task_token.cancel();
+                }
+                restart_count += restarts;
+                command_results.push((attempts, result));
+            }
+        } else {
+            for (i, command) in task.commands.iter().enumerate() {
+                if cancel.is_cancelled() {
+                    command_results.push((0, Err(TaskRunnerError::Cancelled(command.to_string()))));
+                    break;
+                }
+                
+                let expanded_command = expanded_commands[i].clone();
+                let label = format!("{} [{}]", task_name, i + 1);
+                pb.set_message(format!("{} {}", label, &expanded_command));
+                
+                let (attempts, restarts, result) = Self::supervise_command(
+                    &label,
+                    &expanded_command,
+                    command,
+                    task,
+                    &context,
+                    cancel,
+                    pb,
+                ).await;
+                restart_count += restarts;
+                
+                if let Err(e) = &result {
+                    Self::report(pb, format!("  {} {} {}: {}",
+                        "✗".red(), label, expanded_command, e));
+                }
+                
+                let is_err = result.is_err();
+                command_results.push((attempts, result));
+                
+                if is_err && !task.continue_on_error {
                     break;
                 }
             }
@@ -225,18 +861,55 @@ impl TaskExecutor {
         let duration = start_time.elapsed();
         
         // Check if all commands succeeded
-        let success = command_results.iter().all(|r| r.is_ok());
+        let success = command_results.iter().all(|(_, r)| r.is_ok());
+        let interrupted = command_results.iter()
+            .any(|(_, r)| matches!(r, Err(TaskRunnerError::Interrupted(_))));
+        // Stopped because another task failed, without failing itself
+        let cancelled = command_results.iter()
+            .all(|(_, r)| matches!(r, Ok(_) | Err(TaskRunnerError::Cancelled(_))));
+        let attempts = command_results.iter()
+            .map(|(attempts, _)| *attempts)
+            .max()
+            .unwrap_or(0);
         
         if success {
             pb.finish_with_message(format!("{} {} completed in {:.2}s", 
                 "✓".green(), task_name, duration.as_secs_f64()));
+        } else if interrupted {
+            pb.finish_with_message(format!("{} {} interrupted after {:.2}s", 
+                "⚠".yellow(), task_name, duration.as_secs_f64()));
+        } else if cancelled {
+            pb.finish_with_message(format!("{} {} cancelled after {:.2}s", 
+                "⊘".yellow(), task_name, duration.as_secs_f64()));
         } else {
             pb.finish_with_message(format!("{} {} failed in {:.2}s", 
                 "✗".red(), task_name, duration.as_secs_f64()));
         }
         
         if success {
-            Ok(())
+            if let Some(fingerprint) = &fingerprint {
+                run.state.save(task_name, fingerprint)?;
+            }
+            
+            // Only successful runs are stored, and failing to store them never fails the task
+            if let (Some(key), Some(log)) = (&cache_key, &context.log) {
+                let log = log.lock().unwrap().clone();
+                let (cache, key, outputs) = (run.cache.clone(), key.clone(), task.outputs.clone());
+                let base_dir = base_dir.clone();
+                let stored = tokio::task::spawn_blocking(move || {
+                    cache.put(&key, &cache::pack(&base_dir, &outputs, &log)?)
+                }).await.expect("cache upload panicked");
+                
+                if let Err(e) = stored {
+                    Self::report(pb, format!("  {} {} could not be cached: {}", "⚠".yellow(), task_name, e));
+                }
+            }
+            
+            Ok(TaskReport { attempts, restarts: restart_count, ..Default::default() })
+        } else if interrupted {
+            Err(TaskRunnerError::Interrupted(format!("Task '{}'", task_name)))
+        } else if cancelled {
+            Err(TaskRunnerError::Cancelled(format!("Task '{}'", task_name)))
         } else {
             Err(TaskRunnerError::TaskExecutionFailed(
                 format!("Task '{}' failed", task_name)
@@ -244,85 +917,482 @@ impl TaskExecutor {
         }
     }
     
-    /// Execute a single command
+    /// Start a service and wait for it to become ready.
+    ///
+    /// A ready service is handed over to the run, which restarts it according to
+    /// its restart policy and stops it at the end, along with the locks it holds.
+    /// Services don't take job slots, since they would hold them for the whole run.
+    async fn start_service(
+        run: &RunContext,
+        task_name: &str,
+        task: &Task,
+        command: String,
+        context: &CommandContext,
+        lock_permits: Vec<tokio::sync::OwnedSemaphorePermit>,
+        pb: &ProgressBar,
+    ) -> TaskResult {
+        let base_dir = PathBuf::from(context.working_dir.as_deref().unwrap_or("."));
+        let probe = task.ready_when.as_ref()
+            .map(|ready_when| ReadinessProbe::new(ready_when, &base_dir))
+            .transpose()?;
+        let ready_timeout = Duration::from_secs(
+            task.ready_when.as_ref()
+                .and_then(|ready_when| ready_when.timeout)
+                .unwrap_or(service::DEFAULT_READY_TIMEOUT)
+        );
+        
+        let (program, args) = context.shell.build(&command);
+        let launcher = ServiceLauncher {
+            program,
+            args,
+            env: context.env_vars.clone(),
+            working_dir: context.working_dir.clone(),
+            sink: context.sink.clone(),
+            // Log probes need to read the output, which is still shown as it arrives
+            forwarded: matches!(probe, Some(ReadinessProbe::Log(_)))
+                || matches!(context.sink, OutputSink::Prefixed { .. }),
+        };
+        let restart = Restart { policy: task.restart, max_restarts: task.max_restarts };
+        let mut restarts = 0;
+        
+        let start_time = Instant::now();
+        let not_ready = loop {
+            if let Some(status) = &run.status {
+                status.set(task_name, ServiceState::Starting);
+            }
+            
+            let (ready_tx, mut ready_rx) = tokio::sync::watch::channel(false);
+            let taps = OutputTaps {
+                ready: match &probe {
+                    Some(ReadinessProbe::Log(pattern)) => Some((pattern.clone(), ready_tx)),
+                    _ => None,
+                },
+                ..Default::default()
+            };
+            let (mut child, readers) = launcher.spawn(&taps)
+                .map_err(|e| TaskRunnerError::TaskExecutionFailed(format!("{}: {}", command, e)))?;
+            
+            match &probe {
+                Some(probe) => pb.set_message(format!("{} waiting for {}", task_name, probe)),
+                None => pb.set_message(format!("Starting {}", task_name)),
+            }
+            
+            match service::wait_until_ready(
+                &mut child, probe.as_ref(), &mut ready_rx, ready_timeout, &run.cancel
+            ).await {
+                Ok(()) => {
+                    pb.finish_with_message(format!("{} {} ready in {:.2}s",
+                        "●".green(), task_name, start_time.elapsed().as_secs_f64()));
+                    if let Some(status) = &run.status {
+                        status.set(task_name, ServiceState::Up);
+                    }
+                    run.services.add(RunningService {
+                        name: task_name.to_string(),
+                        child,
+                        readers,
+                        launcher,
+                        restart,
+                        restarts,
+                        kill_grace_period: context.kill_grace_period,
+                        kill_token: context.interrupt.kill_token().clone(),
+                        lock_permits,
+                        status: run.status.clone(),
+                    }).await;
+                    return Ok(TaskReport { service: true, ..Default::default() });
+                }
+                // Services that crash while starting up are restarted like those that crash later
+                Err(NotReady::Exited(status)) if restart.allows(status.success(), restarts) => {
+                    restarts += 1;
+                    let delay = service::restart_delay(restarts);
+                    if let Some(status) = &run.status {
+                        status.set(task_name, ServiceState::Restarting);
+                    }
+                    pb.set_message(format!("{} exited ({}), restarting in {}s (restart {})",
+                        task_name, status, delay.as_secs(), restarts));
+                    
+                    tokio::select! {
+                        _ = sleep(delay) => {}
+                        _ = run.cancel.cancelled() => break NotReady::Cancelled,
+                    }
+                }
+                Err(not_ready) => {
+                    let kill_token = context.interrupt.kill_token();
+                    let signal = context.interrupt.signal().unwrap_or(StopSignal::Terminate);
+                    process::terminate(&mut child, signal, context.kill_grace_period, kill_token).await;
+                    break not_ready;
+                }
+            }
+        };
+        
+        if let Some(status) = &run.status {
+            status.set(task_name, ServiceState::Crashed);
+        }
+        
+        let error = match not_ready {
+            NotReady::Exited(status) if restarts > 0 => TaskRunnerError::TaskExecutionFailed(format!(
+                "Service '{}' exited before it was ready: {} (after {} restarts)",
+                task_name, status, restarts
+            )),
+            NotReady::Exited(status) => TaskRunnerError::TaskExecutionFailed(
+                format!("Service '{}' exited before it was ready: {}", task_name, status)
+            ),
+            NotReady::TimedOut => TaskRunnerError::TaskExecutionFailed(format!(
+                "Service '{}' was not ready after {} seconds",
+                task_name, ready_timeout.as_secs()
+            )),
+            NotReady::Cancelled => Self::cancelled_error(&format!("Service '{}'", task_name), context),
+        };
+        
+        if matches!(error, TaskRunnerError::Interrupted(_)) {
+            pb.finish_with_message(format!("{} {} interrupted", "⚠".yellow(), task_name));
+        } else {
+            pb.finish_with_message(format!("{} {}", "✗".red(), error));
+        }
+        Err(error)
+    }
+    
+    /// Restore a task's outputs and replay its logs from the cache.
+    ///
+    /// Returns false when there is no entry for `key`.
+    async fn restore_from_cache(
+        cache: &Arc<TaskCache>,
+        key: &str,
+        base_dir: &std::path::Path,
+        pb: &ProgressBar,
+    ) -> Result<bool, TaskRunnerError> {
+        // Remote backends block on the network
+        let (cache, key) = (cache.clone(), key.to_string());
+        let artifact = tokio::task::spawn_blocking(move || cache.get(&key))
+            .await
+            .expect("cache download panicked")?;
+        
+        let Some(artifact) = artifact else {
+            return Ok(false);
+        };
+        
+        let log = cache::unpack(base_dir, &artifact)?;
+        pb.suspend(|| log.replay())?;
+        Ok(true)
+    }
+    
+    /// Run a `tool_versions` command and return its output
+    async fn tool_version(command: &str, context: &CommandContext) -> Result<String, TaskRunnerError> {
+        let (program, args) = context.shell.build(command);
+        
+        let mut cmd = Command::new(&program);
+        cmd.args(&args).envs(&context.env_vars).stdin(Stdio::null());
+        if let Some(dir) = &context.working_dir {
+            cmd.current_dir(dir);
+        }
+        
+        let output = cmd.output().await
+            .map_err(|e| TaskRunnerError::TaskExecutionFailed(format!("{}: {}", command, e)))?;
+        
+        if !output.status.success() {
+            return Err(TaskRunnerError::TaskExecutionFailed(
+                format!("Tool version command '{}' failed with exit code: {}", command, output.status)
+            ));
+        }
+        
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    
+    /// The local cache, followed by the remote cache if one is configured
+    fn task_cache(&self) -> TaskCache {
+        let cache = TaskCache::new(LocalCache::new(self.config.dir.join(cache::CACHE_DIR)));
+        
+        match &self.config.cache.remote {
+            Some(remote) => {
+                let mut backend = HttpCache::new(&remote.url);
+                if let Some(token) = remote.token_env.as_deref().and_then(|name| std::env::var(name).ok()) {
+                    backend = backend.with_token(token);
+                }
+                cache.with_backend(backend, remote.is_read_only())
+            }
+            None => cache,
+        }
+    }
+    
+    /// Execute a command, retrying it according to its retry policy.
+    ///
+    /// Returns the number of attempts made along with the final result. Each
+    /// failed attempt is logged; cancellation is never retried.
+    async fn execute_command_with_retries(
+        label: &str,
+        expanded_command: &str,
+        command: &TaskCommand,
+        task_policy: &RetryPolicy,
+        context: &CommandContext,
+        cancel: &CancellationToken,
+        pb: &ProgressBar,
+    ) -> (u32, Result<(), TaskRunnerError>) {
+        let policy = command.retry_policy(task_policy);
+        let max_attempts = policy.max_attempts();
+        let mut attempt = 1;
+        
+        loop {
+            let result = Self::execute_command(
+                expanded_command,
+                command.timeout(),
+                context,
+                cancel,
+            ).await;
+            
+            let retryable = !matches!(
+                result,
+                Ok(()) | Err(TaskRunnerError::Cancelled(_)) | Err(TaskRunnerError::Interrupted(_))
+            );
+            
+            if !retryable || attempt >= max_attempts {
+                let result = result.map_err(|e| match e {
+                    TaskRunnerError::TaskExecutionFailed(msg) if attempt > 1 => {
+                        TaskRunnerError::TaskExecutionFailed(
+                            format!("{} (after {} attempts)", msg, attempt)
+                        )
+                    }
+                    e => e,
+                });
+                return (attempt, result);
+            }
+            
+            let delay = policy.delay(attempt);
+            if let Err(e) = &result {
+                Self::report(pb, format!("  {} {} attempt {}/{} failed, retrying in {}: {}",
+                    "↻".yellow(), label, attempt, max_attempts, format_duration(delay), e));
+            }
+            
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = cancel.cancelled() => {
+                    return (attempt, Err(Self::cancelled_error(expanded_command, context)));
+                }
+            }
+            
+            attempt += 1;
+        }
+    }
+    
+    /// Run a command of a task, running it again each time it exits if the
+    /// task's restart policy says so, with the same backoff as services.
+    ///
+    /// Returns the attempts of its last run and the number of restarts along
+    /// with the final result.
+    async fn supervise_command(
+        label: &str,
+        expanded_command: &str,
+        command: &TaskCommand,
+        task: &Task,
+        context: &CommandContext,
+        cancel: &CancellationToken,
+        pb: &ProgressBar,
+    ) -> (u32, u32, Result<(), TaskRunnerError>) {
+        let restart = Restart { policy: task.restart, max_restarts: task.max_restarts };
+        let mut restarts = 0;
+        // Restarts since the command last stayed up for longer than the longest delay
+        let mut consecutive = 0;
+        
+        loop {
+            let started = Instant::now();
+            let (attempts, result) = Self::execute_command_with_retries(
+                label,
+                expanded_command,
+                command,
+                &task.retry,
+                context,
+                cancel,
+                pb,
+            ).await;
+            
+            let stopped = matches!(
+                result,
+                Err(TaskRunnerError::Cancelled(_)) | Err(TaskRunnerError::Interrupted(_))
+            );
+            if stopped || !restart.allows(result.is_ok(), restarts) {
+                let result = result.map_err(|e| match e {
+                    TaskRunnerError::TaskExecutionFailed(msg) if restarts > 0 => {
+                        TaskRunnerError::TaskExecutionFailed(
+                            format!("{} (after {} restarts)", msg, restarts)
+                        )
+                    }
+                    e => e,
+                });
+                return (attempts, restarts, result);
+            }
+            
+            if started.elapsed() >= service::MAX_RESTART_DELAY {
+                consecutive = 0;
+            }
+            consecutive += 1;
+            restarts += 1;
+            
+            let delay = service::restart_delay(consecutive);
+            let exit = match &result {
+                Ok(()) => "exited".to_string(),
+                Err(e) => format!("failed ({})", e),
+            };
+            Self::report(pb, format!("  {} {} {}, restarting in {}s (restart {})",
+                "↻".yellow(), label, exit, delay.as_secs(), restarts));
+            
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = cancel.cancelled() => {
+                    return (attempts, restarts, Err(Self::cancelled_error(expanded_command, context)));
+                }
+            }
+        }
+    }
+    
+    /// Execute a single command through the task's shell
+    ///
+    /// `timeout` overrides the task timeout for this command. On timeout the
+    /// command's process group gets SIGTERM, then SIGKILL once the grace period
+    /// has passed.
     async fn execute_command(
         command: &str,
-        env_vars: &HashMap<String, String>,
-        working_dir: Option<&str>,
         timeout: Option<u64>,
+        context: &CommandContext,
+        cancel: &CancellationToken,
     ) -> Result<(), TaskRunnerError> {
-        let mut parts = command.split_whitespace();
-        let program = parts.next().unwrap_or("");
-        let args: Vec<&str> = parts.collect();
+        // Hold the task's weight in job slots for as long as the command runs
+        let _permit = tokio::select! {
+            permit = context.slots.acquire(context.weight) => permit,
+            _ = cancel.cancelled() => return Err(Self::cancelled_error(command, context)),
+        };
         
-        let mut cmd = Command::new(program);
+        let (program, args) = context.shell.build(command);
+        
+        let mut cmd = Command::new(&program);
         cmd.args(&args);
         
         // Set environment variables
-        for (key, value) in env_vars {
+        for (key, value) in &context.env_vars {
             cmd.env(key, value);
         }
         
         // Set working directory
-        if let Some(dir) = working_dir {
+        if let Some(dir) = &context.working_dir {
             cmd.current_dir(dir);
         }
         
-        // Set up output
-        cmd.stdout(Stdio::inherit());
-        cmd.stderr(Stdio::inherit());
+        // Set up output, reading it ourselves when it is prefixed or captured for the cache
+        let forwarded = context.log.is_some() || matches!(context.sink, OutputSink::Prefixed { .. });
+        if forwarded {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+        } else {
+            cmd.stdout(Stdio::inherit());
+            cmd.stderr(Stdio::inherit());
+        }
         
-        // Execute command with optional timeout
-        if let Some(timeout_secs) = timeout {
-            // Use spawn and poll with timeout support
-            let timeout_duration = Duration::from_secs(timeout_secs);
-            let start = Instant::now();
-            
-            let mut child = cmd.spawn()
-                .map_err(|e| TaskRunnerError::TaskExecutionFailed(e.to_string()))?;
-            
-            // Poll for completion with timeout
-            loop {
-                match child.try_wait() {
-                    Ok(Some(status)) => {
-                        if status.success() {
-                            return Ok(());
-                        } else {
-                            return Err(TaskRunnerError::TaskExecutionFailed(
-                                format!("Command failed with exit code: {}", status)
-                            ));
-                        }
-                    }
-                    Ok(None) => {
-                        // Process still running - check timeout
-                        if start.elapsed() >= timeout_duration {
-                            // Timeout - try to kill the process
-                            let _ = child.kill();
-                            let _ = child.wait(); // Wait for kill to complete
-                            return Err(TaskRunnerError::TaskExecutionFailed(
-                                format!("Command timed out after {} seconds", timeout_secs)
-                            ));
-                        }
-                        // Wait a bit before checking again
-                        sleep(Duration::from_millis(100)).await;
-                    }
-                    Err(e) => {
-                        return Err(TaskRunnerError::TaskExecutionFailed(e.to_string()));
-                    }
+        // Run in a separate process group so signals reach the whole process tree,
+        // and make sure the child never outlives the runner if this future is dropped
+        process::isolate_process_group(&mut cmd);
+        cmd.kill_on_drop(true);
+        
+        let mut child = cmd.spawn()
+            .map_err(|e| TaskRunnerError::TaskExecutionFailed(e.to_string()))?;
+        
+        let taps = OutputTaps {
+            log: context.log.clone(),
+            ..Default::default()
+        };
+        let capture = forwarded.then(|| output::forward_child(&mut child, &context.sink, &taps));
+        
+        let timeout = timeout.or(context.timeout);
+        let timed_out = async {
+            match timeout {
+                Some(timeout_secs) => sleep(Duration::from_secs(timeout_secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        
+        // Wait for exit, the timeout or cancellation, whichever comes first
+        let interrupt = tokio::select! {
+            status = child.wait() => {
+                let status = status
+                    .map_err(|e| TaskRunnerError::TaskExecutionFailed(e.to_string()))?;
+                
+                // Wait for the rest of the command's output
+                for reader in capture.into_iter().flatten() {
+                    let _ = reader.await;
                 }
+                
+                return if status.success() {
+                    Ok(())
+                } else {
+                    Err(TaskRunnerError::TaskExecutionFailed(
+                        format!("Command failed with exit code: {}", status)
+                    ))
+                };
             }
-        } else {
-            // No timeout - execute normally
-            let output = cmd.output()
-                .map_err(|e| TaskRunnerError::TaskExecutionFailed(e.to_string()))?;
-            
-            if output.status.success() {
-                Ok(())
-            } else {
-                Err(TaskRunnerError::TaskExecutionFailed(
-                    format!("Command failed with exit code: {}", output.status)
-                ))
+            _ = timed_out => Interrupt::Timeout,
+            _ = cancel.cancelled() => Interrupt::Cancelled,
+        };
+        
+        let kill_token = context.interrupt.kill_token();
+        
+        match interrupt {
+            Interrupt::Timeout => {
+                let ended_by = process::terminate(
+                    &mut child, StopSignal::Terminate, context.kill_grace_period, kill_token
+                ).await;
+                Err(TaskRunnerError::TaskExecutionFailed(format!(
+                    "Command timed out after {} seconds (stopped by {})",
+                    timeout.unwrap_or_default(), ended_by
+                )))
             }
+            Interrupt::Cancelled => match context.interrupt.signal() {
+                // Forward the runner's own signal to the command's process group
+                Some(signal) => {
+                    let ended_by = process::terminate(
+                        &mut child, signal, context.kill_grace_period, kill_token
+                    ).await;
+                    Err(TaskRunnerError::Interrupted(
+                        format!("{} (stopped by {})", command, ended_by)
+                    ))
+                }
+                None => {
+                    process::terminate(
+                        &mut child, StopSignal::Terminate, context.kill_grace_period, kill_token
+                    ).await;
+                    Err(TaskRunnerError::Cancelled(command.to_string()))
+                }
+            },
+        }
+    }
+    
+    /// Add a spinner for a task, above the status line if there is one
+    fn task_spinner(
+        run: &RunContext,
+        multi_progress: &MultiProgress,
+        style: &ProgressStyle,
+        task_name: &str,
+    ) -> ProgressBar {
+        let pb = match &run.status {
+            Some(status) => multi_progress.insert_before(status.bar(), ProgressBar::new_spinner()),
+            None => multi_progress.add(ProgressBar::new_spinner()),
+        };
+        pb.set_style(style.clone());
+        pb.set_message(format!("Running {}", task_name));
+        pb
+    }
+    
+    /// Print a message above the task's progress bar, or on stderr when the bar
+    /// is hidden because the output isn't a terminal, where indicatif drops it
+    fn report(pb: &ProgressBar, message: String) {
+        if pb.is_hidden() {
+            eprintln!("{}", message);
+        } else {
+            pb.println(message);
+        }
+    }
+    
+    /// Error for a command that was cancelled before it started
+    fn cancelled_error(command: &str, context: &CommandContext) -> TaskRunnerError {
+        if context.interrupt.is_interrupted() {
+            TaskRunnerError::Interrupted(command.to_string())
+        } else {
+            TaskRunnerError::Cancelled(command.to_string())
         }
     }
     
@@ -330,26 +1400,123 @@ impl TaskExecutor {
     fn print_task_details(&self, task_name: &str, task: &Task) {
         println!("  {} {}", "•".green(), task_name.bold());
         
+        // Values set by the environment profile are marked with its name
+        let profile = self.config.active_environment();
+        let overrides = profile.and_then(|(_, profile)| profile.tasks.get(task_name));
+        let origin = |from_profile: bool| match profile {
+            Some((name, _)) if from_profile => format!(" (from {})", name),
+            _ => String::new(),
+        };
+        
         if let Some(desc) = &task.description {
             println!("    Description: {}", desc);
         }
+        if let Some(path) = self.config.user_config.as_ref().filter(|_| task.from_user_config) {
+            println!("    Defined in: {} (user config)", path.display());
+        }
+        if let Some(path) = &task.inherited_from {
+            println!("    Defined in: {} (parent config)", path.display());
+        }
+        if let Some(overrides) = &self.config.local_overrides {
+            if overrides.defined_tasks.iter().any(|name| name == task_name) {
+                println!("    Defined in: {} (local config)", overrides.path.display());
+            } else if let Some(fields) = overrides.tasks.get(task_name) {
+                println!("    Overridden locally: {}", fields.join(", "));
+            }
+        }
         
         println!("    Commands:");
         for (i, cmd) in task.commands.iter().enumerate() {
-            println!("      {}. {}", i + 1, cmd);
+            let mut settings = Vec::new();
+            if let Some(timeout_secs) = cmd.timeout() {
+                settings.push(format!("timeout: {}s", timeout_secs));
+            }
+            if let TaskCommand::Detailed(details) = cmd {
+                if let Some(retries) = details.retry.retries {
+                    settings.push(format!("retries: {}", retries));
+                }
+            }
+            
+            if settings.is_empty() {
+                println!("      {}. {}", i + 1, cmd);
+            } else {
+                println!("      {}. {} ({})", i + 1, cmd, settings.join(", "));
+            }
         }
         
         if !task.dependencies.is_empty() {
             println!("    Dependencies: {}", task.dependencies.join(", "));
         }
         
-        if !task.env.is_empty() {
+        let env_files: Vec<String> = self.config.env_files.iter().chain(&task.env_files)
+            .map(|file| file.to_string())
+            .collect();
+        if !env_files.is_empty() {
+            println!("    Env Files: {}", env_files.join(", "));
+        }
+        
+        if !task.params.is_empty() {
+            println!("    Parameters:");
+            let mut names: Vec<_> = task.params.keys().collect();
+            names.sort();
+            for name in names {
+                let param = &task.params[name];
+                let mut settings = Vec::new();
+                match &param.default {
+                    Some(default) => settings.push(format!("default: {}", default)),
+                    None => settings.push("required".to_string()),
+                }
+                if !param.choices.is_empty() {
+                    settings.push(format!("choices: {}", param.choices.join(", ")));
+                }
+                
+                match &param.description {
+                    Some(desc) => println!("      {} ({}) - {}", name, settings.join(", "), desc),
+                    None => println!("      {} ({})", name, settings.join(", ")),
+                }
+            }
+        }
+        
+        // Global values set by the profile apply unless the task sets its own
+        let mut profile_vars: Vec<&String> = profile
+            .map(|(_, profile)| profile.vars.keys().filter(|name| !task.vars.contains_key(*name)).collect())
+            .unwrap_or_default();
+        profile_vars.sort();
+        if !task.vars.is_empty() || !profile_vars.is_empty() {
+            println!("    Variables:");
+            let mut names: Vec<_> = task.vars.keys().collect();
+            names.sort();
+            for name in names {
+                let from_profile = overrides.is_some_and(|o| o.vars.contains_key(name));
+                println!("      {} = {}{}", name, task.vars[name], origin(from_profile));
+            }
+            for name in profile_vars {
+                println!("      {} = {}{}", name, self.config.vars[name], origin(true));
+            }
+        }
+        
+        let mut profile_env: Vec<&String> = profile
+            .map(|(_, profile)| profile.env.keys().filter(|key| !task.env.contains_key(*key)).collect())
+            .unwrap_or_default();
+        profile_env.sort();
+        if !task.env.is_empty() || !profile_env.is_empty() {
             println!("    Environment:");
             for (key, value) in &task.env {
-                println!("      {}={}", key, value);
+                let from_profile = overrides.is_some_and(|o| o.env.contains_key(key));
+                println!("      {}={}{}", key, value, origin(from_profile));
+            }
+            for key in profile_env {
+                println!("      {}={}{}", key, self.config.env[key], origin(true));
             }
         }
         
+        // Show shell: task-specific, or global from config, or none
+        if let Some(shell) = &task.shell {
+            println!("    Shell: {}", shell);
+        } else if let Some(shell) = &self.config.shell {
+            println!("    Shell: {} (from default)", shell);
+        }
+        
         if task.parallel {
             println!("    Execution: {}", "Parallel".blue());
         } else if task.sequential {
@@ -361,43 +1528,148 @@ impl TaskExecutor {
             .or(self.config.default_timeout);
         if let Some(timeout_secs) = timeout {
             if task.timeout.is_some() {
-                println!("    Timeout: {}s", timeout_secs);
+                let from_profile = overrides.is_some_and(|o| o.timeout.is_some());
+                println!("    Timeout: {}s{}", timeout_secs, origin(from_profile));
+            } else if profile.is_some_and(|(_, profile)| profile.default_timeout.is_some()) {
+                println!("    Timeout: {}s{}", timeout_secs, origin(true));
             } else {
                 println!("    Timeout: {}s (from default)", timeout_secs);
             }
         }
         
-        // Show working directory: task-specific, or default from config, or none
-        let working_dir = task.working_dir.as_deref()
-            .or_else(|| self.config.default_working_dir.as_deref());
-        if let Some(dir) = working_dir {
-            if task.working_dir.is_some() {
-                println!("    Working Directory: {}", dir);
-            } else {
-                println!("    Working Directory: {} (from default)", dir);
+        // Show working directory: task-specific, or default from config, or the
+        // directory of the configuration file when it isn't the current one
+        if let Some(dir) = &task.working_dir {
+            let from_profile = overrides.is_some_and(|o| o.working_dir.is_some());
+            println!("    Working Directory: {}{}", join_path(&task.base_dir, dir), origin(from_profile));
+        } else if let Some(dir) = &self.config.default_working_dir {
+            println!("    Working Directory: {} (from default)", join_path(&self.config.dir, dir));
+        } else if !task.base_dir.as_os_str().is_empty() {
+            println!("    Working Directory: {}", join_path(&task.base_dir, "."));
+        }
+        
+        if !task.sources.is_empty() {
+            println!("    Sources: {}", task.sources.join(", "));
+        }
+        
+        if !task.outputs.is_empty() {
+            println!("    Outputs: {}", task.outputs.join(", "));
+        }
+        
+        if task.service {
+            match &task.ready_when {
+                Some(ready_when) => match ReadinessProbe::new(ready_when, std::path::Path::new(".")) {
+                    Ok(probe) => println!("    Service: ready when {}", probe),
+                    Err(_) => println!("    Service: {}", "Yes".green()),
+                },
+                None => println!("    Service: {}", "Yes".green()),
+            }
+            if task.restart != RestartPolicy::Never {
+                match task.max_restarts {
+                    Some(max_restarts) => println!("    Restart: {} (at most {} times)", task.restart, max_restarts),
+                    None => println!("    Restart: {}", task.restart),
+                }
             }
         }
         
+        if task.cache {
+            println!("    Cache: {}", "Enabled".green());
+            if !task.tool_versions.is_empty() {
+                println!("    Tool Versions: {}", task.tool_versions.join(", "));
+            }
+        }
+        
+        if !task.locks.is_empty() {
+            println!("    Locks: {}", task.locks.join(", "));
+        }
+        
+        if let Some(retries) = task.retry.retries {
+            println!("    Retries: {} ({} backoff, {}s delay{})",
+                retries,
+                match task.retry.retry_backoff.unwrap_or_default() {
+                    RetryBackoff::Fixed => "fixed",
+                    RetryBackoff::Exponential => "exponential",
+                },
+                task.retry.retry_delay.unwrap_or(1),
+                if task.retry.retry_jitter.unwrap_or(false) { ", jitter" } else { "" });
+        }
+        
+        if let Some(weight) = task.weight {
+            println!("    Weight: {}", weight);
+        }
+        
         if task.continue_on_error {
             println!("    Continue on error: {}", "Yes".yellow());
         }
     }
     
     /// Print execution results
-    fn print_execution_results(&self, results: &HashMap<String, Result<(), TaskRunnerError>>) {
+    fn print_execution_results(&self, results: &HashMap<String, TaskResult>) {
         println!();
         println!("{} Execution Results:", "📊".blue());
         println!();
         
         let mut success_count = 0;
         let mut failure_count = 0;
+        let mut skipped_count = 0;
+        let mut interrupted_count = 0;
+        let mut cancelled_count = 0;
+        let mut up_to_date_count = 0;
+        let mut cached_count = 0;
+        let mut restart_count = 0;
         
         for (task_name, result) in results {
             match result {
+                Ok(report) if report.up_to_date => {
+                    println!("  {} {} {}", "≡".blue(), task_name, "(up to date)".dimmed());
+                    up_to_date_count += 1;
+                }
+                Ok(report) if report.service && report.restarts > 0 => {
+                    println!("  {} {} {}", "●".green(), task_name,
+                        format!("(service, restarted {} times)", report.restarts).dimmed());
+                    success_count += 1;
+                    restart_count += report.restarts;
+                }
+                Ok(report) if report.service => {
+                    println!("  {} {} {}", "●".green(), task_name, "(service)".dimmed());
+                    success_count += 1;
+                }
+                Ok(report) if report.restarts > 0 => {
+                    println!("  {} {} {}", "✓".green(), task_name,
+                        format!("(restarted {} times)", report.restarts).dimmed());
+                    success_count += 1;
+                    restart_count += report.restarts;
+                }
+                Ok(report) if report.cached => {
+                    println!("  {} {} {}", "✓".green(), task_name, "(cached)".dimmed());
+                    cached_count += 1;
+                }
+                Ok(report) if report.attempts > 1 => {
+                    println!("  {} {} {}", "✓".green(), task_name,
+                        format!("({} attempts)", report.attempts).dimmed());
+                    success_count += 1;
+                }
                 Ok(_) => {
                     println!("  {} {}", "✓".green(), task_name);
                     success_count += 1;
                 }
+                Err(TaskRunnerError::DependencyFailed(dep, _)) => {
+                    println!("  {} {} {}", "⊘".yellow(), task_name,
+                        format!("(skipped, '{}' failed)", dep).dimmed());
+                    skipped_count += 1;
+                }
+                Err(TaskRunnerError::NotStarted(_)) => {
+                    println!("  {} {} {}", "⊘".yellow(), task_name, "(skipped, run stopped)".dimmed());
+                    skipped_count += 1;
+                }
+                Err(TaskRunnerError::Interrupted(_)) => {
+                    println!("  {} {} {}", "⚠".yellow(), task_name, "(interrupted)".dimmed());
+                    interrupted_count += 1;
+                }
+                Err(TaskRunnerError::Cancelled(_)) => {
+                    println!("  {} {} {}", "⊘".yellow(), task_name, "(cancelled)".dimmed());
+                    cancelled_count += 1;
+                }
                 Err(_) => {
                     println!("  {} {}", "✗".red(), task_name);
                     failure_count += 1;
@@ -406,7 +1678,284 @@ impl TaskExecutor {
         }
         
         println!();
-        println!("  {} {} successful, {} failed", 
-            "📈".blue(), success_count, failure_count);
+        println!("  {} {} successful, {} cached, {} up to date, {} failed, {} skipped", 
+            "📈".blue(), success_count, cached_count, up_to_date_count, failure_count, skipped_count);
+        
+        if restart_count > 0 {
+            println!("  {} {} restarts", "↻".yellow(), restart_count);
+        }
+        
+        if cancelled_count > 0 {
+            println!("  {} {} cancelled", "⊘".yellow(), cancelled_count);
+        }
+        
+        if interrupted_count > 0 {
+            println!("  {} {} interrupted", "⚠".yellow(), interrupted_count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn executor(yaml: &str) -> TaskExecutor {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        TaskExecutor::new(config)
+    }
+    
+    /// An empty scratch directory for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("task-runner-executor-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[tokio::test]
+    async fn test_shell_variables_reach_the_shell() {
+        let dir = temp_dir("shell-vars");
+        let executor = executor(&format!(r#"
+env:
+  OUT: {}
+tasks:
+  loop:
+    commands:
+      - 'for f in a b; do echo "item=$f ${{f%.txt}}" >> "$OUT"; done'
+"#, dir.join("out").display()));
+        
+        executor.run_tasks(&["loop".to_string()], &RunOptions::default()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("out")).unwrap(), "item=a a\nitem=b b\n");
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    fn parallel() -> RunOptions {
+        RunOptions {
+            execution_mode: ExecutionMode::Parallel,
+            jobs: Some(4),
+            ..Default::default()
+        }
+    }
+    
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+    
+    #[tokio::test]
+    async fn test_dependency_order() {
+        let dir = temp_dir("order");
+        let executor = executor(&format!(r#"
+default_working_dir: {}
+tasks:
+  fetch: {{ commands: ["echo fetch >> log"] }}
+  compile: {{ dependencies: [fetch], commands: ["sleep 0.2; echo compile >> log"] }}
+  assets: {{ dependencies: [fetch], commands: ["echo assets >> log"] }}
+  package: {{ dependencies: [compile, assets], commands: ["echo package >> log"] }}
+"#, dir.display()));
+        
+        let (results, _) = executor.execute_run(&names(&["package"]), &parallel()).await.unwrap();
+        assert!(results.values().all(|result| result.is_ok()));
+        
+        let log = std::fs::read_to_string(dir.join("log")).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "fetch");
+        assert_eq!(lines[3], "package");
+        
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-} 
\ No newline at end of file
+    
+    #[tokio::test]
+    async fn test_independent_branches_run_concurrently() {
+        let dir = temp_dir("branches");
+        // Each task only succeeds if the other one starts while it is running
+        let wait_for = |this: &str, other: &str| format!(
+            "touch {}; for i in $(seq 50); do [ -e {} ] && exit 0; sleep 0.1; done; exit 1",
+            this, other
+        );
+        let executor = executor(&format!(r#"
+default_working_dir: {}
+tasks:
+  left: {{ commands: ["{}"] }}
+  right: {{ commands: ["{}"] }}
+"#, dir.display(), wait_for("left", "right"), wait_for("right", "left")));
+        
+        let (results, _) = executor.execute_run(&names(&["left", "right"]), &parallel()).await.unwrap();
+        assert!(results["left"].is_ok());
+        assert!(results["right"].is_ok());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_failure_skips_pending_tasks() {
+        let config = |other_duration: &str| format!(r#"
+tasks:
+  clean: {{ commands: ["sleep 0.2; exit 1"] }}
+  build: {{ dependencies: [clean], commands: ["true"] }}
+  other: {{ commands: ["sleep {}"] }}
+  after: {{ dependencies: [other], commands: ["true"] }}
+"#, other_duration);
+        
+        let started = Instant::now();
+        let (results, _) = executor(&config("10")).execute_run(&names(&["build", "after"]), &parallel()).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        
+        assert!(matches!(results["clean"], Err(TaskRunnerError::TaskExecutionFailed(_))));
+        assert!(matches!(&results["build"], Err(TaskRunnerError::DependencyFailed(dep, _)) if dep == "clean"));
+        assert!(matches!(results["other"], Err(TaskRunnerError::Cancelled(_))));
+        assert!(matches!(results["after"], Err(TaskRunnerError::NotStarted(_))));
+        
+        // With continue_on_error, independent branches keep going
+        let options = RunOptions { continue_on_error: true, ..parallel() };
+        let (results, _) = executor(&config("0.5")).execute_run(&names(&["build", "after"]), &options).await.unwrap();
+        assert!(matches!(&results["build"], Err(TaskRunnerError::DependencyFailed(..))));
+        assert!(results["other"].is_ok());
+        assert!(results["after"].is_ok());
+    }
+    
+    #[tokio::test]
+    async fn test_parallel_commands_restart() {
+        let dir = temp_dir("restart");
+        // `flaky` fails on its first run only, `broken` on every run
+        let executor = executor(&format!(r#"
+default_working_dir: {}
+tasks:
+  dev:
+    commands:
+      - "echo run >> flaky; [ $(wc -l < flaky) -ge 2 ]"
+      - "true"
+    parallel: true
+    restart: on-failure
+  crashing:
+    commands: ["echo run >> broken; exit 1"]
+    parallel: true
+    restart: on-failure
+    max_restarts: 1
+"#, dir.display()));
+        
+        let (results, _) = executor.execute_run(&names(&["dev"]), &parallel()).await.unwrap();
+        assert_eq!(results["dev"].as_ref().unwrap().restarts, 1);
+        assert_eq!(std::fs::read_to_string(dir.join("flaky")).unwrap().lines().count(), 2);
+        
+        let (results, _) = executor.execute_run(&names(&["crashing"]), &parallel()).await.unwrap();
+        assert!(matches!(results["crashing"], Err(TaskRunnerError::TaskExecutionFailed(_))));
+        assert_eq!(std::fs::read_to_string(dir.join("broken")).unwrap().lines().count(), 2);
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_locks_in_any_order_do_not_deadlock() {
+        // Each task would wait on the other if they took their locks in the order listed
+        let executor = executor(r#"
+tasks:
+  migrate: { locks: [db, cache], commands: ["sleep 0.2"] }
+  seed: { locks: [cache, db], commands: ["sleep 0.2"] }
+"#);
+        
+        let (task_names, options) = (names(&["migrate", "seed"]), parallel());
+        let run = executor.execute_run(&task_names, &options);
+        let (results, _) = tokio::time::timeout(Duration::from_secs(5), run).await
+            .expect("tasks taking the same locks deadlocked")
+            .unwrap();
+        assert!(results["migrate"].is_ok());
+        assert!(results["seed"].is_ok());
+    }
+    
+    #[tokio::test]
+    async fn test_parallel_commands_overlap() {
+        let dir = temp_dir("overlap");
+        // Each command only succeeds if the other one starts while it is running
+        let wait_for = |this: &str, other: &str| format!(
+            "touch {}; for i in $(seq 50); do [ -e {} ] && exit 0; sleep 0.1; done; exit 1",
+            this, other
+        );
+        let executor = executor(&format!(r#"
+default_working_dir: {}
+tasks:
+  both:
+    commands: ["{}", "{}"]
+    parallel: true
+"#, dir.display(), wait_for("first", "second"), wait_for("second", "first")));
+        
+        let (results, _) = executor.execute_run(&names(&["both"]), &parallel()).await.unwrap();
+        assert!(results["both"].is_ok());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_parallel_command_failure_cancels_siblings() {
+        let dir = temp_dir("siblings");
+        let config = |continue_on_error: bool| format!(r#"
+default_working_dir: {}
+tasks:
+  both:
+    commands: ["sleep 0.2; exit 1", "sleep 3; touch finished"]
+    parallel: true
+    continue_on_error: {}
+"#, dir.display(), continue_on_error);
+        
+        let started = Instant::now();
+        let (results, _) = executor(&config(false)).execute_run(&names(&["both"]), &parallel()).await.unwrap();
+        assert!(matches!(results["both"], Err(TaskRunnerError::TaskExecutionFailed(_))));
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(!dir.join("finished").exists());
+        
+        // With continue_on_error, the other command runs to the end
+        let (results, _) = executor(&config(true)).execute_run(&names(&["both"]), &parallel()).await.unwrap();
+        assert!(results["both"].is_err());
+        assert!(dir.join("finished").exists());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_timeout() {
+        let context = |kill_grace_period: Duration| CommandContext {
+            shell: Shell::resolve(None, None).unwrap(),
+            env_vars: std::env::vars().collect(),
+            working_dir: None,
+            timeout: None,
+            kill_grace_period,
+            interrupt: InterruptHandle::default(),
+            slots: JobSlots::new(1),
+            weight: 1,
+            sink: OutputSink::Terminal,
+            log: None,
+        };
+        let timeout_error = |result: Result<(), TaskRunnerError>| match result {
+            Err(TaskRunnerError::TaskExecutionFailed(message)) => message,
+            result => panic!("expected the command to time out, got {:?}", result),
+        };
+        let cancel = CancellationToken::new();
+        
+        let started = Instant::now();
+        let patient = context(Duration::from_secs(5));
+        let result = TaskExecutor::execute_command("sleep 10", Some(1), &patient, &cancel).await;
+        assert_eq!(timeout_error(result), "Command timed out after 1 seconds (stopped by SIGTERM)");
+        assert!(started.elapsed() < Duration::from_secs(6));
+        
+        // A command ignoring SIGTERM is killed once the grace period has passed
+        let context = context(Duration::from_millis(300));
+        let result = TaskExecutor::execute_command("trap '' TERM; sleep 10", Some(1), &context, &cancel).await;
+        assert_eq!(timeout_error(result), "Command timed out after 1 seconds (stopped by SIGKILL)");
+        
+        // The command's own timeout applies instead of the task's
+        let executor = executor(r#"
+tasks:
+  slow:
+    timeout: 60
+    commands:
+      - { run: "sleep 10", timeout: 1 }
+"#);
+        let started = Instant::now();
+        let (results, _) = executor.execute_run(&names(&["slow"]), &RunOptions::default()).await.unwrap();
+        assert!(matches!(results["slow"], Err(TaskRunnerError::TaskExecutionFailed(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
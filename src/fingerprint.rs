@@ -0,0 +1,192 @@
+use crate::error::TaskRunnerError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Directory, relative to the project, where task state is stored
+pub const STATE_DIR: &str = ".task-runner/state";
+
+/// Expand glob patterns relative to `base`, returning the matching files in sorted order
+pub fn expand_globs(base: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, TaskRunnerError> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let full_pattern = base.join(pattern);
+        let matches = glob::glob(&full_pattern.to_string_lossy())
+            .map_err(|e| TaskRunnerError::ConfigParseError(
+                format!("Invalid glob pattern '{}': {}", pattern, e)
+            ))?;
+
+        for entry in matches {
+            let path = entry.map_err(|e| TaskRunnerError::IoError(e.into()))?;
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Hex encoded SHA-256 of a file's contents
+pub fn hash_file(path: &Path) -> Result<String, TaskRunnerError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Everything that determines whether a task needs to run again
+#[derive(Debug, Default)]
+pub struct FingerprintInputs<'a> {
+    pub commands: Vec<String>,
+    pub env: BTreeMap<&'a str, &'a str>,
+    pub working_dir: Option<&'a str>,
+    pub shell: String,
+    pub source_patterns: &'a [String],
+}
+
+/// Compute a task's fingerprint from its inputs and the contents of its sources
+pub fn compute(base: &Path, inputs: &FingerprintInputs) -> Result<String, TaskRunnerError> {
+    let mut hasher = Sha256::new();
+
+    // Length-prefix every field so that adjacent values can't run into each other
+    let mut write = |value: &str| {
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value.as_bytes());
+    };
+
+    for command in &inputs.commands {
+        write(command);
+    }
+    for (key, value) in &inputs.env {
+        write(key);
+        write(value);
+    }
+    write(inputs.working_dir.unwrap_or(""));
+    write(&inputs.shell);
+
+    for path in expand_globs(base, inputs.source_patterns)? {
+        let relative = path.strip_prefix(base).unwrap_or(&path);
+        write(&relative.to_string_lossy());
+        write(&hash_file(&path)?);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TaskState {
+    fingerprint: String,
+}
+
+/// Stores the fingerprint of each task's last successful run
+#[derive(Debug, Clone)]
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+impl StateStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Fingerprint recorded by the task's last successful run
+    pub fn load(&self, task_name: &str) -> Option<String> {
+        let content = std::fs::read_to_string(self.path(task_name)).ok()?;
+        let state: TaskState = serde_json::from_str(&content).ok()?;
+        Some(state.fingerprint)
+    }
+
+    /// Record the fingerprint of a successful run
+    pub fn save(&self, task_name: &str, fingerprint: &str) -> Result<(), TaskRunnerError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let state = TaskState {
+            fingerprint: fingerprint.to_string(),
+        };
+        std::fs::write(self.path(task_name), serde_json::to_string_pretty(&state)?)?;
+        Ok(())
+    }
+
+    fn path(&self, task_name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", encode_file_name(task_name)))
+    }
+}
+
+/// Encode a task name (which may contain `:`) as a portable file name
+pub(crate) fn encode_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c.to_string()
+            } else {
+                format!("%{:02X}", c as u32)
+            }
+        })
+        .collect()
+}
+
+/// Whether every output pattern matches at least one file
+pub fn outputs_exist(base: &Path, patterns: &[String]) -> Result<bool, TaskRunnerError> {
+    for pattern in patterns {
+        if expand_globs(base, std::slice::from_ref(pattern))?.is_empty() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Sort an environment map so that it hashes the same way every time
+pub fn sorted_env(env: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    env.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_file_name() {
+        assert_eq!(encode_file_name("build"), "build");
+        assert_eq!(encode_file_name("deploy:prod"), "deploy%3Aprod");
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_sources_and_commands() {
+        let base = std::env::temp_dir().join(format!("task-runner-fp-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("src")).unwrap();
+        std::fs::write(base.join("src/a.txt"), "a").unwrap();
+
+        let sources = vec!["src/*.txt".to_string()];
+        let inputs = FingerprintInputs {
+            commands: vec!["cat src/a.txt".to_string()],
+            source_patterns: &sources,
+            ..Default::default()
+        };
+
+        let first = compute(&base, &inputs).unwrap();
+        assert_eq!(compute(&base, &inputs).unwrap(), first);
+
+        // Changing a source changes the fingerprint
+        std::fs::write(base.join("src/a.txt"), "b").unwrap();
+        let second = compute(&base, &inputs).unwrap();
+        assert_ne!(second, first);
+
+        // So does changing a command
+        let changed = FingerprintInputs {
+            commands: vec!["cat src/*.txt".to_string()],
+            source_patterns: &sources,
+            ..Default::default()
+        };
+        assert_ne!(compute(&base, &changed).unwrap(), second);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,10 +1,24 @@
+pub mod cache;
 pub mod config;
+pub mod dotenv;
 pub mod executor;
+pub mod fingerprint;
 pub mod error;
+pub mod local;
+pub mod output;
+pub mod params;
+pub mod process;
+pub mod resources;
+pub mod service;
+pub mod shell;
 pub mod task;
+pub mod template;
 pub mod utils;
+pub mod vars;
+pub mod watch;
 
 pub use config::Config;
-pub use executor::TaskExecutor;
+pub use executor::{RunOptions, TaskExecutor};
 pub use error::TaskRunnerError;
-pub use task::{Task, ExecutionMode}; 
\ No newline at end of file
+pub use shell::Shell;
+pub use task::{Task, TaskCommand, Param, RetryPolicy, ReadyWhen, RestartPolicy, ExecutionMode}; 
\ No newline at end of file
@@ -0,0 +1,247 @@
+use crate::error::TaskRunnerError;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Key of a map that appends its list to the list it replaces (`!append` in YAML)
+pub const APPEND: &str = "!append";
+
+/// What a local override file changed in the configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalOverrides {
+    /// The local file
+    pub path: PathBuf,
+
+    /// Configuration fields outside of tasks, as dotted paths (e.g. `env.PORT`)
+    pub fields: Vec<String>,
+
+    /// Fields of existing tasks, by task
+    pub tasks: HashMap<String, Vec<String>>,
+
+    /// Tasks only defined in the local file
+    pub defined_tasks: Vec<String>,
+}
+
+impl LocalOverrides {
+    fn record(&mut self, keys: &[String], note: &str) {
+        match keys {
+            [tasks, task] if tasks == "tasks" && note.is_empty() => {
+                self.defined_tasks.push(task.clone());
+            }
+            [tasks, task, field @ ..] if tasks == "tasks" && !field.is_empty() => {
+                self.tasks.entry(task.clone()).or_default().push(format!("{}{}", field.join("."), note));
+            }
+            _ => self.fields.push(format!("{}{}", keys.join("."), note)),
+        }
+    }
+}
+
+/// The local override files looked for next to `config_path`, in order:
+/// `task-runner.local.json`, `.yaml`, `.yml` and `.toml` for `task-runner.yaml`
+pub fn candidates(config_path: &Path) -> Vec<PathBuf> {
+    let dir = config_path.parent().unwrap_or(Path::new(""));
+    let stem = config_path.file_stem().unwrap_or_default().to_string_lossy();
+    ["json", "yaml", "yml", "toml"].iter()
+        .map(|ext| dir.join(format!("{}.local.{}", stem, ext)))
+        .collect()
+}
+
+/// The local override file of `config_path`, if there is one
+pub fn find(config_path: &Path) -> Option<PathBuf> {
+    candidates(config_path).into_iter().find(|path| path.is_file())
+}
+
+/// Read a configuration file of any supported format as a JSON value, with
+/// YAML `!append` tags turned into `{ "!append": [...] }`
+pub fn parse_value(path: &Path) -> Result<Value, TaskRunnerError> {
+    let content = std::fs::read_to_string(path)?;
+    let error = |e: String| TaskRunnerError::ConfigParseError(format!("{}: {}", path.display(), e));
+
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(|e| error(e.to_string())),
+        Some("yaml") | Some("yml") => {
+            let value: serde_yaml::Value = serde_yaml::from_str(&content).map_err(|e| error(e.to_string()))?;
+            from_yaml(value).map_err(error)
+        }
+        Some("toml") => toml::from_str(&content).map_err(|e| error(e.to_string())),
+        _ => Err(error("Unsupported file format. Use .json, .yaml, .yml, or .toml".to_string())),
+    }
+}
+
+fn from_yaml(value: serde_yaml::Value) -> Result<Value, String> {
+    use serde_yaml::Value as Yaml;
+
+    Ok(match value {
+        Yaml::Null => Value::Null,
+        Yaml::Bool(b) => Value::Bool(b),
+        Yaml::Number(n) => serde_json::to_value(n).map_err(|e| e.to_string())?,
+        Yaml::String(s) => Value::String(s),
+        Yaml::Sequence(items) => Value::Array(items.into_iter().map(from_yaml).collect::<Result<_, _>>()?),
+        Yaml::Mapping(mapping) => {
+            let mut map = Map::new();
+            for (key, value) in mapping {
+                let key = match key {
+                    Yaml::String(s) => s,
+                    Yaml::Number(n) => n.to_string(),
+                    Yaml::Bool(b) => b.to_string(),
+                    key => return Err(format!("unsupported map key {:?}", key)),
+                };
+                map.insert(key, from_yaml(value)?);
+            }
+            Value::Object(map)
+        }
+        Yaml::Tagged(tagged) if tagged.tag == "append" => {
+            let mut map = Map::new();
+            map.insert(APPEND.to_string(), from_yaml(tagged.value)?);
+            Value::Object(map)
+        }
+        Yaml::Tagged(tagged) => return Err(format!("unsupported tag {}", tagged.tag)),
+    })
+}
+
+/// Deep-merge the local file's `local` value into the configuration's `base`.
+///
+/// Maps merge key by key, any other value (lists included, so a task's
+/// `commands`) replaces the one it overrides, `null` removes it, and
+/// `{ "!append": [...] }` appends to the list it overrides.
+pub fn merge(base: &mut Value, local: Value, path: &Path) -> Result<LocalOverrides, TaskRunnerError> {
+    let error = |e: String| TaskRunnerError::ConfigParseError(format!("{}: {}", path.display(), e));
+    let (Value::Object(base), Value::Object(local)) = (base, local) else {
+        return Err(error("expected a map of configuration fields".to_string()));
+    };
+
+    let mut overrides = LocalOverrides {
+        path: path.to_path_buf(),
+        ..Default::default()
+    };
+    merge_map(base, local, &mut Vec::new(), &mut overrides).map_err(error)?;
+    Ok(overrides)
+}
+
+fn merge_map(
+    base: &mut Map<String, Value>,
+    local: Map<String, Value>,
+    keys: &mut Vec<String>,
+    overrides: &mut LocalOverrides,
+) -> Result<(), String> {
+    for (key, value) in local {
+        keys.push(key.clone());
+
+        if value.is_null() {
+            base.remove(&key);
+            overrides.record(keys, " (removed)");
+        } else if let Some(items) = append_items(&value, keys)? {
+            match base.entry(key).or_insert_with(|| Value::Array(Vec::new())) {
+                Value::Array(list) => list.extend(items),
+                _ => return Err(format!("{}: only lists can be appended to", keys.join("."))),
+            }
+            overrides.record(keys, " (appended)");
+        } else {
+            match (base.get_mut(&key), value) {
+                (Some(Value::Object(existing)), Value::Object(map)) => {
+                    merge_map(existing, map, keys, overrides)?;
+                }
+                (Some(existing), value) => {
+                    *existing = resolve_appends(value, keys)?;
+                    overrides.record(keys, "");
+                }
+                (None, value) => {
+                    base.insert(key, resolve_appends(value, keys)?);
+                    overrides.record(keys, "");
+                }
+            }
+        }
+
+        keys.pop();
+    }
+    Ok(())
+}
+
+/// The items of an `{ "!append": [...] }` value
+fn append_items(value: &Value, keys: &[String]) -> Result<Option<Vec<Value>>, String> {
+    match value {
+        Value::Object(map) if map.len() == 1 => match map.get(APPEND) {
+            Some(Value::Array(items)) => items.iter()
+                .map(|item| resolve_appends(item.clone(), keys))
+                .collect::<Result<_, _>>()
+                .map(Some),
+            Some(_) => Err(format!("{}: {} needs a list", keys.join("."), APPEND)),
+            None => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// A value that overrides nothing, with its appends turned into plain lists
+fn resolve_appends(value: Value, keys: &[String]) -> Result<Value, String> {
+    if let Some(items) = append_items(&value, keys)? {
+        return Ok(Value::Array(items));
+    }
+    Ok(match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| Ok((key, resolve_appends(value, keys)?)))
+                .collect::<Result<_, String>>()?
+        ),
+        Value::Array(items) => Value::Array(
+            items.into_iter().map(|item| resolve_appends(item, keys)).collect::<Result<_, _>>()?
+        ),
+        value => value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut base: Value = serde_json::from_str(r#"{
+            "env": { "PORT": "8080", "HOST": "localhost" },
+            "default_timeout": 60,
+            "tasks": {
+                "serve": {
+                    "commands": ["npm start"],
+                    "dependencies": ["build"],
+                    "env": { "DEBUG": "0" }
+                },
+                "build": { "commands": ["npm run build"], "timeout": 30 }
+            }
+        }"#).unwrap();
+        let local: serde_yaml::Value = serde_yaml::from_str(r#"
+env:
+  PORT: "3000"
+default_timeout: null
+tasks:
+  serve:
+    commands: ["npm run dev"]
+    dependencies: !append [lint]
+    env:
+      DEBUG: "1"
+  lint:
+    commands: ["npm run lint"]
+"#).unwrap();
+
+        let overrides = merge(&mut base, from_yaml(local).unwrap(), Path::new("task-runner.local.yaml")).unwrap();
+        let expected: Value = serde_json::from_str(r#"{
+            "env": { "PORT": "3000", "HOST": "localhost" },
+            "tasks": {
+                "serve": {
+                    "commands": ["npm run dev"],
+                    "dependencies": ["build", "lint"],
+                    "env": { "DEBUG": "1" }
+                },
+                "build": { "commands": ["npm run build"], "timeout": 30 },
+                "lint": { "commands": ["npm run lint"] }
+            }
+        }"#).unwrap();
+        assert_eq!(base, expected);
+
+        assert_eq!(overrides.fields, ["default_timeout (removed)", "env.PORT"]);
+        assert_eq!(overrides.tasks["serve"], ["commands", "dependencies (appended)", "env.DEBUG"]);
+        assert_eq!(overrides.defined_tasks, ["lint"]);
+
+        let append_to_map = serde_json::json!({ "env": { APPEND: ["X=1"] } });
+        assert!(merge(&mut base, append_to_map, Path::new("task-runner.local.json")).is_err());
+    }
+}
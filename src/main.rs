@@ -1,7 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use colored::*;
+use std::path::Path;
 use std::process::exit;
-use task_runner::{config::Config, executor::TaskExecutor, error::TaskRunnerError};
+use std::time::Duration;
+use task_runner::cache::{self, LocalCache};
+use task_runner::{config::{Config, LoadOptions}, params, executor::{RunOptions, TaskExecutor}, error::TaskRunnerError, process::SignalListener, watch};
 
 #[derive(Parser)]
 #[command(
@@ -14,17 +17,22 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// Configuration file path (default: task-runner.json, task-runner.yaml, or task-runner.toml)
-    #[arg(short, long)]
+    /// Configuration file path (default: the nearest task-runner.json, task-runner.yaml,
+    /// or task-runner.toml in the current directory or its parents)
+    #[arg(short, long, env = Config::CONFIG_ENV)]
     config: Option<String>,
 
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
 
-    /// Environment to use for task execution
-    #[arg(short, long)]
+    /// Environment profile to apply (one of `environments` in the configuration)
+    #[arg(short, long, global = true, env = Config::ENVIRONMENT_ENV)]
     env: Option<String>,
+
+    /// Don't merge the user configuration (~/.config/task-runner/config.yaml)
+    #[arg(long, global = true)]
+    no_user_config: bool,
 }
 
 #[derive(Subcommand)]
@@ -37,28 +45,139 @@ enum Commands {
     },
     /// Run one or more tasks
     Run {
-        /// Task names to run
-        tasks: Vec<String>,
-        
-        /// Run tasks in parallel
-        #[arg(short, long)]
-        parallel: bool,
+        #[command(flatten)]
+        args: RunArgs,
         
-        /// Run tasks sequentially (default)
+        /// Re-run the tasks whenever their sources change
         #[arg(short, long)]
-        sequential: bool,
+        watch: bool,
+    },
+    /// Run tasks, then re-run them whenever their sources change
+    Watch {
+        #[command(flatten)]
+        args: RunArgs,
+    },
+    /// Start services and other long-running tasks side by side until Ctrl-C,
+    /// prefixing each line of output with its task's name
+    Up {
+        /// Tasks to start (default: every service)
+        tasks: Vec<String>,
         
-        /// Continue execution even if some tasks fail
-        #[arg(short, long)]
-        continue_on_error: bool,
+        /// Maximum number of commands running at once (default: number of CPUs)
+        #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        jobs: Option<u64>,
     },
     /// Show task information
     Info {
         /// Task name
         task: String,
     },
+    /// Print the environment a task runs with and where each value comes from
+    Env {
+        /// Task name, optionally setting parameters (`task:key=value`)
+        task: String,
+        
+        /// Also print variables inherited from the process environment
+        #[arg(short, long)]
+        all: bool,
+    },
     /// Validate configuration file
     Validate,
+    /// Inspect and clean up the local task output cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Task names to run, optionally setting parameters (`task:key=value`)
+    tasks: Vec<String>,
+    
+    /// Set a parameter of every task named that declares it
+    #[arg(long = "param", value_name = "KEY=VALUE")]
+    params: Vec<String>,
+    
+    /// Arguments appended to the last command of each task named (after `--`)
+    #[arg(last = true, value_name = "ARGS")]
+    args: Vec<String>,
+    
+    /// Run tasks in parallel
+    #[arg(short, long)]
+    parallel: bool,
+    
+    /// Run tasks sequentially (default)
+    #[arg(short, long)]
+    sequential: bool,
+    
+    /// Continue execution even if some tasks fail
+    #[arg(short, long)]
+    continue_on_error: bool,
+    
+    /// Time limit for the whole run (in seconds)
+    #[arg(long, value_name = "SECS")]
+    run_timeout: Option<u64>,
+    
+    /// Maximum number of commands running at once (default: number of CPUs)
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+    
+    /// Run tasks even if their sources are unchanged
+    #[arg(short, long)]
+    force: bool,
+}
+
+impl RunArgs {
+    /// The names of the tasks to run and the options to run them with
+    fn parse(&self) -> Result<(Vec<String>, RunOptions), TaskRunnerError> {
+        let (tasks, mut params) = params::parse_task_args(&self.tasks)?;
+        if tasks.is_empty() {
+            return Err(TaskRunnerError::NoTasksSpecified);
+        }
+        for assignment in &self.params {
+            let (key, value) = params::parse_assignment(assignment)?;
+            params.shared.insert(key, value);
+        }
+        
+        let execution_mode = if self.parallel {
+            task_runner::ExecutionMode::Parallel
+        } else if self.sequential {
+            task_runner::ExecutionMode::Sequential
+        } else {
+            task_runner::ExecutionMode::Auto
+        };
+        
+        let options = RunOptions {
+            execution_mode,
+            continue_on_error: self.continue_on_error,
+            run_timeout: self.run_timeout,
+            jobs: self.jobs.map(|jobs| jobs as usize),
+            force: self.force,
+            params,
+            extra_args: self.args.clone(),
+            ..Default::default()
+        };
+        Ok((tasks, options))
+    }
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show the number of entries and the size of the cache
+    Info,
+    /// Remove entries that are old or don't fit in the size limit
+    Prune {
+        /// Remove entries not used in this many days
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u64>,
+        
+        /// Remove the least recently used entries until the cache fits (in MB)
+        #[arg(long, value_name = "MB")]
+        max_size: Option<u64>,
+    },
+    /// Remove every entry
+    Clear,
 }
 
 #[tokio::main]
@@ -70,45 +189,149 @@ async fn main() {
     
     if let Err(e) = run(cli).await {
         eprintln!("{} {}", "Error:".red().bold(), e);
-        exit(1);
+        
+        // Follow the shell convention of 128 + SIGINT for interrupted runs
+        match e {
+            TaskRunnerError::Interrupted(_) => exit(130),
+            _ => exit(1),
+        }
     }
 }
 
 async fn run(cli: Cli) -> Result<(), TaskRunnerError> {
-    let config = Config::load(cli.config.as_deref())?;
+    // The cache lives next to the configuration file, but doesn't need it to be valid
+    if let Commands::Cache { action } = &cli.command {
+        let config_dir = Config::locate(cli.config.as_deref()).ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        return run_cache_command(action, &config_dir);
+    }
+    
+    let load_options = LoadOptions {
+        environment: cli.env.clone(),
+        user_config: !cli.no_user_config,
+    };
+    let (config, _) = Config::load_with_path(cli.config.as_deref(), &load_options)?;
+    let services = config.get_services();
     let executor = TaskExecutor::new(config);
     
     match cli.command {
         Commands::List { details } => {
             executor.list_tasks(details).await?;
         }
-        Commands::Run { 
-            tasks, 
-            parallel, 
-            sequential, 
-            continue_on_error 
-        } => {
+        Commands::Run { args, watch: true } | Commands::Watch { args } => {
+            let (tasks, options) = args.parse()?;
+            watch::watch(cli.config.as_deref(), &load_options, &tasks, &options).await?;
+        }
+        Commands::Run { args, watch: false } => {
+            let (tasks, options) = args.parse()?;
+            forward_signals(&executor)?;
+            executor.run_tasks(&tasks, &options).await?;
+        }
+        Commands::Up { tasks, jobs } => {
+            let tasks = if tasks.is_empty() { services } else { tasks };
             if tasks.is_empty() {
                 return Err(TaskRunnerError::NoTasksSpecified);
             }
             
-            let execution_mode = if parallel {
-                task_runner::ExecutionMode::Parallel
-            } else if sequential {
-                task_runner::ExecutionMode::Sequential
-            } else {
-                task_runner::ExecutionMode::Auto
+            let options = RunOptions {
+                execution_mode: task_runner::ExecutionMode::Parallel,
+                jobs: jobs.map(|jobs| jobs as usize),
+                prefix_output: true,
+                keep_services: true,
+                ..Default::default()
             };
             
-            executor.run_tasks(&tasks, execution_mode, continue_on_error).await?;
+            forward_signals(&executor)?;
+            match executor.run_tasks(&tasks, &options).await {
+                // Ctrl-C is how `up` is meant to end
+                Err(TaskRunnerError::Interrupted(_)) => {}
+                result => result?,
+            }
         }
         Commands::Info { task } => {
             executor.show_task_info(&task).await?;
         }
+        Commands::Env { task, all } => {
+            let (names, params) = params::parse_task_args(&[task])?;
+            let given = params.per_task.get(&names[0]).cloned().unwrap_or_default();
+            executor.show_task_env(&names[0], &given, all).await?;
+        }
         Commands::Validate => {
             println!("{} Configuration file is valid!", "✓".green());
         }
+        Commands::Cache { .. } => unreachable!("handled before loading the configuration"),
     }
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Forward the first Ctrl-C/SIGTERM to running commands, force-kill on the second
+fn forward_signals(executor: &TaskExecutor) -> Result<(), TaskRunnerError> {
+    let interrupt = executor.interrupt_handle();
+    let mut signals = SignalListener::new()?;
+    tokio::spawn(async move {
+        loop {
+            let signal = signals.recv().await;
+            if interrupt.trigger(signal) {
+                eprintln!("{} Received {}, stopping tasks (press Ctrl-C again to force)...",
+                    "⚠️".yellow(), signal);
+            } else {
+                eprintln!("{} Forcing shutdown...", "⚠️".yellow());
+            }
+        }
+    });
+    Ok(())
+}
+
+fn run_cache_command(action: &CacheAction, config_dir: &Path) -> Result<(), TaskRunnerError> {
+    let cache = LocalCache::new(config_dir.join(cache::CACHE_DIR));
+    
+    match action {
+        CacheAction::Info => {
+            let stats = cache.stats()?;
+            println!("{} Cache: {}", "📦".blue(), cache.dir().display());
+            println!("  Entries: {}", stats.entries);
+            println!("  Size: {}", cache::format_size(stats.bytes));
+        }
+        CacheAction::Prune { max_age, max_size } => {
+            if max_age.is_none() && max_size.is_none() {
+                return Err(TaskRunnerError::CacheError(
+                    "specify --max-age and/or --max-size".to_string()
+                ));
+            }
+            
+            let removed = cache.prune(
+                max_age.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                max_size.map(|mb| mb * 1024 * 1024),
+            )?;
+            println!("{} Removed {} entries ({})",
+                "✓".green(), removed.entries, cache::format_size(removed.bytes));
+        }
+        CacheAction::Clear => {
+            let removed = cache.clear()?;
+            println!("{} Removed {} entries ({})",
+                "✓".green(), removed.entries, cache::format_size(removed.bytes));
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_env() {
+        use clap::CommandFactory;
+
+        // --config falls back to TASK_RUNNER_CONFIG, checked without touching the process env
+        let command = Cli::command();
+        let config = command.get_arguments().find(|arg| arg.get_id() == "config").unwrap();
+        assert_eq!(config.get_env(), Some(std::ffi::OsStr::new(Config::CONFIG_ENV)));
+
+        let cli = Cli::try_parse_from(["task-runner", "--config", "task-runner.toml", "list"]).unwrap();
+        assert_eq!(cli.config.as_deref(), Some("task-runner.toml"));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::cache::{CapturedLog, LogStream};
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::Child;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Colors cycled through for task name prefixes
+const PREFIX_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::BrightRed,
+];
+
+/// Prints output lines prefixed with the name of the task they came from,
+/// padded so that the lines of every task line up
+pub struct PrefixedOutput {
+    multi: MultiProgress,
+    width: usize,
+    colors: BTreeMap<String, Color>,
+}
+
+impl PrefixedOutput {
+    pub fn new(multi: MultiProgress, task_names: &[String]) -> Self {
+        let mut names = task_names.to_vec();
+        names.sort();
+
+        Self {
+            multi,
+            width: names.iter().map(|name| name.chars().count()).max().unwrap_or(0),
+            colors: names.into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, PREFIX_COLORS[i % PREFIX_COLORS.len()]))
+                .collect(),
+        }
+    }
+
+    /// Print one line of a task's output above the progress bars
+    pub fn line(&self, task_name: &str, stream: LogStream, line: &[u8]) {
+        let color = self.colors.get(task_name).copied().unwrap_or(Color::White);
+        let prefix = format!("{:width$} |", task_name, width = self.width).color(color);
+        let text = String::from_utf8_lossy(line);
+        self.print(stream, &format!("{} {}", prefix, text.trim_end_matches(['\n', '\r'])));
+    }
+
+    /// Print a message of the runner's own above the progress bars
+    pub fn message(&self, message: &str) {
+        self.print(LogStream::Stdout, message);
+    }
+
+    fn print(&self, stream: LogStream, line: &str) {
+        // Bars aren't drawn when the output isn't a terminal, and neither is their println
+        if self.multi.is_hidden() {
+            match stream {
+                LogStream::Stdout => println!("{}", line),
+                LogStream::Stderr => eprintln!("{}", line),
+            }
+        } else {
+            let _ = self.multi.println(line);
+        }
+    }
+}
+
+/// Where a command's output goes
+#[derive(Clone)]
+pub enum OutputSink {
+    /// Straight to the runner's stdout and stderr
+    Terminal,
+    /// Line by line, prefixed with the task's name
+    Prefixed {
+        output: Arc<PrefixedOutput>,
+        task_name: String,
+    },
+}
+
+impl OutputSink {
+    fn write(&self, stream: LogStream, data: &[u8]) {
+        match self {
+            OutputSink::Terminal => {
+                let _ = match stream {
+                    LogStream::Stdout => std::io::stdout().write_all(data),
+                    LogStream::Stderr => std::io::stderr().write_all(data),
+                };
+            }
+            OutputSink::Prefixed { output, task_name } => output.line(task_name, stream, data),
+        }
+    }
+
+    /// Print a message of the runner's own about the task
+    pub fn message(&self, message: &str) {
+        match self {
+            OutputSink::Terminal => eprintln!("{}", message),
+            OutputSink::Prefixed { output, .. } => output.message(message),
+        }
+    }
+}
+
+/// What to do with a command's output besides showing it
+#[derive(Clone, Default)]
+pub struct OutputTaps {
+    /// Record the output, for cached tasks
+    pub log: Option<Arc<Mutex<CapturedLog>>>,
+    /// Flag the sender once a line matches the pattern, for log readiness probes
+    pub ready: Option<(Regex, watch::Sender<bool>)>,
+}
+
+/// Forward a command's output from `reader` to `sink` until it is closed
+pub fn forward<R>(reader: R, stream: LogStream, sink: OutputSink, taps: OutputTaps) -> JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let by_line = matches!(sink, OutputSink::Prefixed { .. }) || taps.ready.is_some();
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+
+        loop {
+            buf.clear();
+            let read = if by_line {
+                reader.read_until(b'\n', &mut buf).await
+            } else {
+                buf.resize(8192, 0);
+                let read = reader.read(&mut buf).await;
+                buf.truncate(*read.as_ref().unwrap_or(&0));
+                read
+            };
+            if matches!(read, Ok(0) | Err(_)) {
+                break;
+            }
+
+            sink.write(stream, &buf);
+            if let Some(log) = &taps.log {
+                log.lock().unwrap().push(stream, &buf);
+            }
+            if let Some((pattern, ready)) = &taps.ready {
+                if !*ready.borrow() && pattern.is_match(&String::from_utf8_lossy(&buf)) {
+                    ready.send_replace(true);
+                }
+            }
+        }
+    })
+}
+
+/// Forward a child's piped stdout and stderr to `sink`
+pub fn forward_child(child: &mut Child, sink: &OutputSink, taps: &OutputTaps) -> Vec<JoinHandle<()>> {
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(forward(stdout, LogStream::Stdout, sink.clone(), taps.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(forward(stderr, LogStream::Stderr, sink.clone(), taps.clone()));
+    }
+    readers
+}
+
+/// State of a service shown in the status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    Starting,
+    Up,
+    Restarting,
+    Exited,
+    Crashed,
+}
+
+/// A line below the other progress bars summarizing the state of every service
+pub struct StatusLine {
+    pb: ProgressBar,
+    states: Mutex<BTreeMap<String, ServiceState>>,
+}
+
+impl StatusLine {
+    pub fn new(multi: &MultiProgress) -> Self {
+        let pb = multi.add(ProgressBar::new_spinner());
+        pb.set_style(ProgressStyle::default_spinner().template("{wide_msg}").unwrap());
+        Self {
+            pb,
+            states: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The status line's bar, which other bars are inserted above
+    pub fn bar(&self) -> &ProgressBar {
+        &self.pb
+    }
+
+    pub fn set(&self, task_name: &str, state: ServiceState) {
+        let mut states = self.states.lock().unwrap();
+        states.insert(task_name.to_string(), state);
+
+        let summary: Vec<String> = states.iter()
+            .map(|(name, state)| match state {
+                ServiceState::Starting => format!("{} {} starting", "◌".yellow(), name),
+                ServiceState::Up => format!("{} {} up", "●".green(), name),
+                ServiceState::Restarting => format!("{} {} restarting", "↻".yellow(), name),
+                ServiceState::Exited => format!("{} {} exited", "○".dimmed(), name),
+                ServiceState::Crashed => format!("{} {} crashed", "✗".red(), name),
+            })
+            .collect();
+        self.pb.set_message(summary.join("  "));
+    }
+
+    pub fn finish(&self) {
+        self.pb.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::ProgressDrawTarget;
+
+    #[tokio::test]
+    async fn test_forward_taps_output() {
+        let (ready_tx, ready_rx) = watch::channel(false);
+        let taps = OutputTaps {
+            log: Some(Default::default()),
+            ready: Some((Regex::new("listening on \\d+").unwrap(), ready_tx)),
+        };
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let sink = OutputSink::Prefixed {
+            output: Arc::new(PrefixedOutput::new(multi, &["api".to_string()])),
+            task_name: "api".to_string(),
+        };
+
+        let output: &[u8] = b"starting\nlistening on 8080\n";
+        forward(output, LogStream::Stdout, sink, taps.clone()).await.unwrap();
+
+        assert!(*ready_rx.borrow());
+        let mut expected = CapturedLog::default();
+        expected.push(LogStream::Stdout, output);
+        assert_eq!(*taps.log.unwrap().lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::error::TaskRunnerError;
+use crate::task::Task;
+use std::collections::HashMap;
+
+/// Parameter values given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct ParamArgs {
+    /// Values for every task named on the command line that declares the
+    /// parameter (`--param key=value`)
+    pub shared: HashMap<String, String>,
+    /// Values for a single task (`task:key=value`)
+    pub per_task: HashMap<String, HashMap<String, String>>,
+}
+
+/// Whether `name` can be used as a parameter name
+pub fn is_valid_param_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Split a `key=value` assignment
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), TaskRunnerError> {
+    match assignment.split_once('=') {
+        Some((key, value)) if is_valid_param_name(key) => Ok((key.to_string(), value.to_string())),
+        _ => Err(TaskRunnerError::ParamError(
+            format!("'{}' is not a valid parameter assignment (expected key=value)", assignment)
+        )),
+    }
+}
+
+/// Split command line task arguments such as `build` and `build:target=release`
+/// into task names, in order and without duplicates, and the parameter values
+/// set for each task
+pub fn parse_task_args(args: &[String]) -> Result<(Vec<String>, ParamArgs), TaskRunnerError> {
+    let mut names = Vec::new();
+    let mut params = ParamArgs::default();
+
+    for arg in args {
+        // Parameters follow the last colon before the `=`
+        let (name, assignment) = match arg.split_once('=') {
+            Some((before, _)) => match before.rsplit_once(':') {
+                Some((name, _)) => (name, Some(&arg[name.len() + 1..])),
+                None => (arg.as_str(), None),
+            },
+            None => (arg.as_str(), None),
+        };
+
+        if let Some(assignment) = assignment {
+            let (key, value) = parse_assignment(assignment)?;
+            params.per_task.entry(name.to_string()).or_default().insert(key, value);
+        }
+        if !names.iter().any(|existing| existing == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    Ok((names, params))
+}
+
+/// Resolve the value of each of a task's parameters from the values given on
+/// the command line, falling back to their defaults
+pub fn resolve(
+    task_name: &str,
+    task: &Task,
+    given: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, TaskRunnerError> {
+    if let Some(key) = given.keys().find(|key| !task.params.contains_key(*key)) {
+        return Err(TaskRunnerError::ParamError(
+            format!("Task '{}' has no parameter '{}'", task_name, key)
+        ));
+    }
+
+    let mut values = HashMap::new();
+    for (name, param) in &task.params {
+        let value = given.get(name).or(param.default.as_ref()).ok_or_else(|| {
+            TaskRunnerError::ParamError(format!(
+                "Task '{}' needs a value for parameter '{}' (use {}:{}=<value>)",
+                task_name, name, task_name, name
+            ))
+        })?;
+
+        if !param.choices.is_empty() && !param.choices.contains(value) {
+            return Err(TaskRunnerError::ParamError(format!(
+                "Parameter '{}' of task '{}' must be one of {}, not '{}'",
+                name, task_name, param.choices.join(", "), value
+            )));
+        }
+        values.insert(name.clone(), value.clone());
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Param;
+
+    #[test]
+    fn test_parse_task_args() {
+        let args = ["build:target=release", "test", "build:jobs=4", "ns:lint:fix=a=b"]
+            .map(String::from);
+        let (names, params) = parse_task_args(&args).unwrap();
+
+        assert_eq!(names, vec!["build", "test", "ns:lint"]);
+        assert_eq!(params.per_task["build"]["target"], "release");
+        assert_eq!(params.per_task["build"]["jobs"], "4");
+        assert_eq!(params.per_task["ns:lint"]["fix"], "a=b");
+        assert!(!params.per_task.contains_key("test"));
+        assert!(parse_task_args(&["build:=x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut task = Task::new(vec!["cargo build --profile {{ params.target }}".to_string()]);
+        task.params.insert("target".to_string(), Param {
+            description: None,
+            default: Some("debug".to_string()),
+            choices: vec!["debug".to_string(), "release".to_string()],
+        });
+
+        let values = resolve("build", &task, &HashMap::new()).unwrap();
+        assert_eq!(values["target"], "debug");
+
+        let given = HashMap::from([("target".to_string(), "release".to_string())]);
+        assert_eq!(resolve("build", &task, &given).unwrap()["target"], "release");
+
+        let given = HashMap::from([("target".to_string(), "fast".to_string())]);
+        assert!(resolve("build", &task, &given).is_err());
+        let given = HashMap::from([("other".to_string(), "x".to_string())]);
+        assert!(resolve("build", &task, &given).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
+
+/// Default time to wait after asking a process to stop before killing it (in seconds)
+pub const DEFAULT_KILL_GRACE_PERIOD: u64 = 5;
+
+/// Signal used to stop a running command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopSignal {
+    /// SIGINT (Ctrl-C)
+    Interrupt,
+    /// SIGTERM
+    Terminate,
+    /// SIGKILL
+    Kill,
+}
+
+impl StopSignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StopSignal::Interrupt => "SIGINT",
+            StopSignal::Terminate => "SIGTERM",
+            StopSignal::Kill => "SIGKILL",
+        }
+    }
+
+    #[cfg(unix)]
+    fn as_raw(&self) -> libc::c_int {
+        match self {
+            StopSignal::Interrupt => libc::SIGINT,
+            StopSignal::Terminate => libc::SIGTERM,
+            StopSignal::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+impl std::fmt::Display for StopSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Shared state for interrupting a run from a signal handler.
+///
+/// The first interrupt records the signal and cancels the stop token so that
+/// running commands receive the same signal; any further interrupt cancels the
+/// kill token, which skips the grace period and kills them immediately.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    stop: CancellationToken,
+    kill: CancellationToken,
+    signal: Arc<OnceLock<StopSignal>>,
+}
+
+impl InterruptHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an interrupt, returning `true` if it was the first one
+    pub fn trigger(&self, signal: StopSignal) -> bool {
+        if self.signal.set(signal).is_ok() {
+            self.stop.cancel();
+            true
+        } else {
+            self.kill.cancel();
+            false
+        }
+    }
+
+    /// The signal of the first interrupt, if the run has been interrupted
+    pub fn signal(&self) -> Option<StopSignal> {
+        self.signal.get().copied()
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.signal.get().is_some()
+    }
+
+    /// Token cancelled on the first interrupt
+    pub fn stop_token(&self) -> &CancellationToken {
+        &self.stop
+    }
+
+    /// Token cancelled on the second interrupt
+    pub fn kill_token(&self) -> &CancellationToken {
+        &self.kill
+    }
+}
+
+/// Listens for Ctrl-C (and SIGTERM on Unix) delivered to the runner
+pub struct SignalListener {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl SignalListener {
+    pub fn new() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Self {})
+        }
+    }
+
+    /// Wait for the next signal
+    pub async fn recv(&mut self) -> StopSignal {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => StopSignal::Interrupt,
+                _ = self.terminate.recv() => StopSignal::Terminate,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+            StopSignal::Interrupt
+        }
+    }
+}
+
+/// Start the command in its own process group so the whole tree can be signalled.
+///
+/// The group is in the background as far as the terminal is concerned, so
+/// reading from the terminal would stop the command with SIGTTIN. Its stdin is
+/// `/dev/null` instead, and commands that read input get end of file.
+pub fn isolate_process_group(cmd: &mut Command) {
+    cmd.stdin(std::process::Stdio::null());
+    #[cfg(unix)]
+    cmd.process_group(0);
+}
+
+/// Send a signal to a process group created by `isolate_process_group`
+fn signal_process_group(child: &mut Child, pgid: Option<u32>, signal: StopSignal) {
+    #[cfg(unix)]
+    {
+        let _ = child;
+        if let Some(pgid) = pgid {
+            // SAFETY: killpg only sends a signal and has no memory safety requirements
+            unsafe {
+                libc::killpg(pgid as libc::pid_t, signal.as_raw());
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (pgid, signal);
+        let _ = child.start_kill();
+    }
+}
+
+/// Whether any process is left in the group
+fn process_group_alive(pgid: Option<u32>) -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: signal 0 only checks for the existence of the group
+        pgid.is_some_and(|pgid| unsafe { libc::killpg(pgid as libc::pid_t, 0) } == 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pgid;
+        false
+    }
+}
+
+/// Wait for the child and then for the rest of its process group to exit
+async fn wait_for_process_group(child: &mut Child, pgid: Option<u32>) {
+    let _ = child.wait().await;
+    while process_group_alive(pgid) {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Stop a child's whole process tree: send `signal` to its process group, wait
+/// up to `grace_period` for the group to exit, then kill it. Returns the signal
+/// that ended the command.
+///
+/// The grace period is cut short when `kill_token` is cancelled.
+pub async fn terminate(
+    child: &mut Child,
+    signal: StopSignal,
+    grace_period: Duration,
+    kill_token: &CancellationToken,
+) -> StopSignal {
+    let pgid = child.id();
+
+    if signal != StopSignal::Kill {
+        signal_process_group(child, pgid, signal);
+
+        let exited = tokio::select! {
+            _ = wait_for_process_group(child, pgid) => true,
+            _ = tokio::time::sleep(grace_period) => false,
+            _ = kill_token.cancelled() => false,
+        };
+
+        if exited {
+            return signal;
+        }
+    }
+
+    signal_process_group(child, pgid, StopSignal::Kill);
+    let _ = child.kill().await;
+    StopSignal::Kill
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger() {
+        let handle = InterruptHandle::new();
+        assert!(!handle.is_interrupted());
+
+        // The first interrupt stops commands, keeping its signal
+        assert!(handle.trigger(StopSignal::Terminate));
+        assert_eq!(handle.signal(), Some(StopSignal::Terminate));
+        assert!(handle.stop_token().is_cancelled());
+        assert!(!handle.kill_token().is_cancelled());
+
+        // The second one kills them
+        assert!(!handle.trigger(StopSignal::Interrupt));
+        assert_eq!(handle.signal(), Some(StopSignal::Terminate));
+        assert!(handle.kill_token().is_cancelled());
+    }
+
+    fn spawn(script: &str) -> Child {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        isolate_process_group(&mut cmd);
+        cmd.kill_on_drop(true);
+        cmd.spawn().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_terminate() {
+        let never = CancellationToken::new();
+
+        let mut child = spawn("sleep 10");
+        let signal = terminate(&mut child, StopSignal::Terminate, Duration::from_secs(5), &never).await;
+        assert_eq!(signal, StopSignal::Terminate);
+
+        // A command ignoring SIGTERM is killed after the grace period
+        let mut child = spawn("trap '' TERM; sleep 10");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let started = std::time::Instant::now();
+        let signal = terminate(&mut child, StopSignal::Terminate, Duration::from_millis(300), &never).await;
+        assert_eq!(signal, StopSignal::Kill);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // ... or as soon as the kill token is cancelled
+        let mut child = spawn("trap '' TERM; sleep 10");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let kill = CancellationToken::new();
+        kill.cancel();
+        let started = std::time::Instant::now();
+        let signal = terminate(&mut child, StopSignal::Terminate, Duration::from_secs(10), &kill).await;
+        assert_eq!(signal, StopSignal::Kill);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::error::TaskRunnerError;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Job slots shared by every command of a run, limiting how many run at once
+#[derive(Debug, Clone)]
+pub struct JobSlots {
+    semaphore: Arc<Semaphore>,
+    total: u32,
+}
+
+impl JobSlots {
+    pub fn new(jobs: usize) -> Self {
+        let total = u32::try_from(jobs.max(1)).unwrap_or(u32::MAX);
+        Self {
+            semaphore: Arc::new(Semaphore::new(total as usize)),
+            total,
+        }
+    }
+
+    /// Slots for a run: `jobs` from `--jobs`, then the config's `max_parallel`,
+    /// then the number of jobs for this machine
+    pub fn configured(jobs: Option<usize>, max_parallel: Option<usize>) -> Self {
+        Self::new(jobs.or(max_parallel).unwrap_or_else(Self::default_jobs))
+    }
+
+    /// Number of jobs for this machine, used when no limit is configured
+    pub fn default_jobs() -> usize {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    }
+
+    /// Total number of slots
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Wait for `weight` slots; a weight above the limit takes every slot
+    pub async fn acquire(&self, weight: u32) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_many_owned(weight.clamp(1, self.total))
+            .await
+            .expect("job slots are never closed")
+    }
+}
+
+/// Named locks shared by every task of a run.
+///
+/// Each lock is a counting semaphore whose capacity comes from the config's
+/// `locks` section, defaulting to 1 (a mutex).
+#[derive(Debug, Clone, Default)]
+pub struct ResourceLocks {
+    locks: Arc<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl ResourceLocks {
+    /// Create a lock for every name, using the configured capacities
+    pub fn new<'a>(
+        names: impl IntoIterator<Item = &'a String>,
+        capacities: &HashMap<String, usize>,
+    ) -> Self {
+        let locks = names
+            .into_iter()
+            .map(|name| {
+                let capacity = capacities.get(name).copied().unwrap_or(1).max(1);
+                (name.clone(), Arc::new(Semaphore::new(capacity)))
+            })
+            .collect();
+
+        Self {
+            locks: Arc::new(locks),
+        }
+    }
+
+    /// The order in which to take `names`: sorted, so that tasks sharing
+    /// locks never wait on each other in a cycle, and without duplicates
+    pub fn order(names: &[String]) -> Vec<&String> {
+        let mut names: Vec<&String> = names.iter().collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Take the lock without waiting, if it is free
+    pub fn try_acquire(&self, name: &str) -> Result<Option<OwnedSemaphorePermit>, TaskRunnerError> {
+        Ok(self.semaphore(name)?.try_acquire_owned().ok())
+    }
+
+    /// Wait for the lock
+    pub async fn acquire(&self, name: &str) -> Result<OwnedSemaphorePermit, TaskRunnerError> {
+        Ok(self.semaphore(name)?
+            .acquire_owned()
+            .await
+            .expect("resource locks are never closed"))
+    }
+
+    fn semaphore(&self, name: &str) -> Result<Arc<Semaphore>, TaskRunnerError> {
+        self.locks
+            .get(name)
+            .cloned()
+            .ok_or_else(|| TaskRunnerError::UnknownLock(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_configured_jobs() {
+        assert_eq!(JobSlots::configured(Some(2), Some(8)).total(), 2);
+        assert_eq!(JobSlots::configured(None, Some(8)).total(), 8);
+        assert_eq!(JobSlots::configured(None, None).total() as usize, JobSlots::default_jobs());
+        assert_eq!(JobSlots::configured(Some(0), None).total(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_weight_above_limit_takes_every_slot() {
+        let slots = JobSlots::new(2);
+
+        // Waiting for more slots than there are would never finish
+        let heavy = timeout(Duration::from_secs(1), slots.acquire(5)).await
+            .expect("a weight above the limit should take every slot");
+        assert_eq!(slots.semaphore.available_permits(), 0);
+        assert!(timeout(Duration::from_millis(50), slots.acquire(1)).await.is_err());
+
+        drop(heavy);
+        assert_eq!(slots.semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn test_lock_capacity() {
+        let names = ["db".to_string(), "gpu".to_string()];
+        let capacities = HashMap::from([("gpu".to_string(), 2)]);
+        let locks = ResourceLocks::new(&names, &capacities);
+
+        // A lock without a capacity is a mutex
+        let db = locks.try_acquire("db").unwrap();
+        assert!(db.is_some());
+        assert!(locks.try_acquire("db").unwrap().is_none());
+        drop(db);
+        assert!(locks.try_acquire("db").unwrap().is_some());
+
+        // A lock with a capacity is held by that many tasks at once
+        let first = locks.try_acquire("gpu").unwrap();
+        let second = locks.try_acquire("gpu").unwrap();
+        assert!(first.is_some() && second.is_some());
+        assert!(locks.try_acquire("gpu").unwrap().is_none());
+
+        assert!(matches!(locks.try_acquire("network"), Err(TaskRunnerError::UnknownLock(name)) if name == "network"));
+    }
+
+    #[test]
+    fn test_lock_order() {
+        let names = ["gpu".to_string(), "db".to_string(), "gpu".to_string(), "cache".to_string()];
+        assert_eq!(ResourceLocks::order(&names), ["cache", "db", "gpu"]);
+    }
+}
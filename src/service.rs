@@ -0,0 +1,418 @@
+use crate::error::TaskRunnerError;
+use crate::output::{self, OutputSink, OutputTaps, ServiceState, StatusLine};
+use crate::process::{self, StopSignal};
+use crate::task::{ReadyWhen, RestartPolicy};
+use colored::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, OnceLock};
+use tokio::process::{Child, Command};
+use tokio::sync::{watch, Mutex, OwnedSemaphorePermit};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Default time to wait for a service to become ready (in seconds)
+pub const DEFAULT_READY_TIMEOUT: u64 = 60;
+
+/// How often a readiness probe is checked
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a single TCP or HTTP check may take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the rest of a service's output once it has exited
+const OUTPUT_FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Delay before the first restart of a service that exited
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between restarts; a service that stayed up for longer
+/// starts over from the shortest delay
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+
+/// A check that tells whether a service is ready, built from `ready_when`
+#[derive(Debug, Clone)]
+pub enum ReadinessProbe {
+    /// A TCP port accepts connections
+    Port { host: String, port: u16 },
+    /// A URL responds with HTTP 200
+    Http(String),
+    /// A line of output matches
+    Log(Regex),
+    /// A file exists
+    File(PathBuf),
+}
+
+impl ReadinessProbe {
+    /// Build the probe, resolving `file` against the service's working directory
+    pub fn new(ready_when: &ReadyWhen, working_dir: &Path) -> Result<Self, TaskRunnerError> {
+        if let Some(port) = ready_when.port {
+            let host = ready_when.host.clone().unwrap_or_else(|| "localhost".to_string());
+            Ok(ReadinessProbe::Port { host, port })
+        } else if let Some(url) = &ready_when.http {
+            Ok(ReadinessProbe::Http(url.clone()))
+        } else if let Some(pattern) = &ready_when.log {
+            Regex::new(pattern)
+                .map(ReadinessProbe::Log)
+                .map_err(|e| TaskRunnerError::ConfigParseError(e.to_string()))
+        } else if let Some(file) = &ready_when.file {
+            Ok(ReadinessProbe::File(working_dir.join(file)))
+        } else {
+            Err(TaskRunnerError::ConfigParseError(
+                "ready_when needs one of port, http, log or file".to_string()
+            ))
+        }
+    }
+
+    /// Check the probe once; `log_matched` tells whether a matching line has been seen
+    async fn check(&self, log_matched: bool) -> bool {
+        match self {
+            ReadinessProbe::Port { host, port } => {
+                let connect = tokio::net::TcpStream::connect((host.as_str(), *port));
+                matches!(timeout(CHECK_TIMEOUT, connect).await, Ok(Ok(_)))
+            }
+            ReadinessProbe::Http(url) => {
+                let url = url.clone();
+                let ok = tokio::task::spawn_blocking(move || {
+                    let agent = ureq::AgentBuilder::new().timeout(CHECK_TIMEOUT).build();
+                    matches!(agent.get(&url).call(), Ok(response) if response.status() == 200)
+                }).await;
+                ok.unwrap_or(false)
+            }
+            ReadinessProbe::Log(_) => log_matched,
+            ReadinessProbe::File(path) => path.exists(),
+        }
+    }
+}
+
+impl std::fmt::Display for ReadinessProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadinessProbe::Port { host, port } => write!(f, "port {}:{}", host, port),
+            ReadinessProbe::Http(url) => write!(f, "HTTP 200 from {}", url),
+            ReadinessProbe::Log(regex) => write!(f, "log line matching /{}/", regex),
+            ReadinessProbe::File(path) => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+/// Why a service never became ready
+#[derive(Debug)]
+pub enum NotReady {
+    Exited(ExitStatus),
+    TimedOut,
+    Cancelled,
+}
+
+/// Wait until `probe` passes, the service exits, `ready_timeout` expires or the run is cancelled.
+///
+/// `log_ready` flips to true once a line of output has matched a log probe.
+pub async fn wait_until_ready(
+    child: &mut Child,
+    probe: Option<&ReadinessProbe>,
+    log_ready: &mut watch::Receiver<bool>,
+    ready_timeout: Duration,
+    cancel: &CancellationToken,
+) -> Result<(), NotReady> {
+    let Some(probe) = probe else {
+        return Ok(());
+    };
+
+    let deadline = Instant::now() + ready_timeout;
+
+    loop {
+        let log_matched = *log_ready.borrow();
+        if probe.check(log_matched).await {
+            return Ok(());
+        }
+
+        tokio::select! {
+            status = child.wait() => {
+                return Err(match status {
+                    Ok(status) => NotReady::Exited(status),
+                    Err(_) => NotReady::Cancelled,
+                });
+            }
+            _ = sleep(PROBE_INTERVAL) => {}
+            // Check log probes as soon as the line is seen
+            Ok(()) = log_ready.changed() => {}
+            _ = tokio::time::sleep_until(deadline) => return Err(NotReady::TimedOut),
+            _ = cancel.cancelled() => return Err(NotReady::Cancelled),
+        }
+    }
+}
+
+/// Everything needed to start a service's process, again each time it is restarted
+pub struct ServiceLauncher {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<String>,
+    pub sink: OutputSink,
+    /// Pipe the service's output through `sink` rather than letting it write to the terminal
+    pub forwarded: bool,
+}
+
+impl ServiceLauncher {
+    /// Start the service in its own process group, forwarding its output through `taps`.
+    ///
+    /// Returns the child along with the tasks forwarding its output.
+    pub fn spawn(&self, taps: &OutputTaps) -> std::io::Result<(Child, Vec<JoinHandle<()>>)> {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args).envs(&self.env);
+        if let Some(dir) = &self.working_dir {
+            cmd.current_dir(dir);
+        }
+        process::isolate_process_group(&mut cmd);
+        cmd.kill_on_drop(true);
+        if self.forwarded {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+        }
+
+        let mut child = cmd.spawn()?;
+        let readers = output::forward_child(&mut child, &self.sink, taps);
+        Ok((child, readers))
+    }
+}
+
+/// Restart settings of a service or of the commands of a parallel task
+#[derive(Debug, Clone, Copy)]
+pub struct Restart {
+    pub policy: RestartPolicy,
+    pub max_restarts: Option<u32>,
+}
+
+impl Restart {
+    /// Whether a process restarted `restarts` times so far is restarted again
+    /// after exiting, successfully or not
+    pub fn allows(&self, success: bool, restarts: u32) -> bool {
+        self.policy.restarts(success)
+            && self.max_restarts.is_none_or(|max_restarts| restarts < max_restarts)
+    }
+}
+
+/// Delay before a restart, doubling with every consecutive restart (1 for the first)
+pub fn restart_delay(consecutive: u32) -> Duration {
+    RESTART_DELAY
+        .saturating_mul(2u32.saturating_pow(consecutive.saturating_sub(1)))
+        .min(MAX_RESTART_DELAY)
+}
+
+/// A service that became ready and runs until the end of the run
+pub struct RunningService {
+    pub name: String,
+    pub child: Child,
+    /// Tasks forwarding the output of `child`
+    pub readers: Vec<JoinHandle<()>>,
+    pub launcher: ServiceLauncher,
+    pub restart: Restart,
+    /// Times the service was restarted before it became ready
+    pub restarts: u32,
+    pub kill_grace_period: Duration,
+    pub kill_token: CancellationToken,
+    /// Resource locks held for as long as the service runs
+    pub lock_permits: Vec<OwnedSemaphorePermit>,
+    pub status: Option<Arc<StatusLine>>,
+}
+
+impl RunningService {
+    fn set_state(&self, state: ServiceState) {
+        if let Some(status) = &self.status {
+            status.set(&self.name, state);
+        }
+    }
+}
+
+/// How the supervision of a service ended
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServiceOutcome {
+    /// Times the service was restarted
+    pub restarts: u32,
+    /// Exit status of a service that exited for good before it was stopped
+    pub exited: Option<ExitStatus>,
+}
+
+/// Keep a service running according to its restart policy until `stop` is
+/// cancelled, then stop it with the signal recorded in `signal`
+async fn supervise(
+    mut service: RunningService,
+    stop: CancellationToken,
+    signal: Arc<OnceLock<StopSignal>>,
+) -> ServiceOutcome {
+    let mut restarts = service.restarts;
+    // Restarts since the service last stayed up for longer than the longest delay
+    let mut consecutive = restarts;
+
+    loop {
+        let started = Instant::now();
+        let status = tokio::select! {
+            status = service.child.wait() => status,
+            _ = stop.cancelled() => {
+                let signal = signal.get().copied().unwrap_or(StopSignal::Terminate);
+                process::terminate(
+                    &mut service.child, signal, service.kill_grace_period, &service.kill_token
+                ).await;
+                return ServiceOutcome { restarts, exited: None };
+            }
+        };
+        let Ok(status) = status else {
+            return ServiceOutcome { restarts, exited: None };
+        };
+
+        // Show the last lines of output before saying the service exited; processes
+        // left behind by the service may keep the pipes open, so don't wait for long
+        let readers = std::mem::take(&mut service.readers);
+        let _ = timeout(OUTPUT_FLUSH_TIMEOUT, futures::future::join_all(readers)).await;
+
+        if !service.restart.allows(status.success(), restarts) {
+            service.set_state(if status.success() { ServiceState::Exited } else { ServiceState::Crashed });
+            return ServiceOutcome { restarts, exited: Some(status) };
+        }
+
+        if started.elapsed() >= MAX_RESTART_DELAY {
+            consecutive = 0;
+        }
+        consecutive += 1;
+        restarts += 1;
+
+        let delay = restart_delay(consecutive);
+        service.set_state(ServiceState::Restarting);
+        service.launcher.sink.message(&format!("{} Service '{}' exited ({}), restarting in {}s",
+            "↻".yellow(), service.name, status, delay.as_secs()));
+
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = stop.cancelled() => return ServiceOutcome { restarts, exited: None },
+        }
+
+        // Restarted services are considered up straight away; dependents are already running
+        match service.launcher.spawn(&OutputTaps::default()) {
+            Ok((child, readers)) => {
+                service.child = child;
+                service.readers = readers;
+                service.set_state(ServiceState::Up);
+            }
+            Err(e) => {
+                service.launcher.sink.message(&format!("{} Failed to restart service '{}': {}",
+                    "✗".red(), service.name, e));
+                service.set_state(ServiceState::Crashed);
+                return ServiceOutcome { restarts, exited: Some(status) };
+            }
+        }
+    }
+}
+
+/// Background task keeping a service running
+struct Supervisor {
+    name: String,
+    handle: JoinHandle<ServiceOutcome>,
+}
+
+/// Services started by a run, each supervised in the background until the
+/// run stops them
+#[derive(Clone, Default)]
+pub struct Services {
+    supervisors: Arc<Mutex<Vec<Supervisor>>>,
+    stop: CancellationToken,
+    signal: Arc<OnceLock<StopSignal>>,
+}
+
+impl Services {
+    pub async fn add(&self, service: RunningService) {
+        let name = service.name.clone();
+        let handle = tokio::spawn(supervise(service, self.stop.clone(), self.signal.clone()));
+        self.supervisors.lock().await.push(Supervisor { name, handle });
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.supervisors.lock().await.is_empty()
+    }
+
+    /// Wait until every service has exited for good or `cancel` is cancelled
+    pub async fn wait(&self, cancel: &CancellationToken) {
+        loop {
+            let all_exited = self.supervisors.lock().await.iter()
+                .all(|supervisor| supervisor.handle.is_finished());
+            if all_exited {
+                return;
+            }
+
+            tokio::select! {
+                _ = sleep(PROBE_INTERVAL) => {}
+                _ = cancel.cancelled() => return,
+            }
+        }
+    }
+
+    /// Stop every service, sending `signal` to each and killing those still
+    /// running after their grace period. Returns how each service's
+    /// supervision ended, by name.
+    pub async fn stop_all(&self, signal: StopSignal) -> HashMap<String, ServiceOutcome> {
+        let supervisors = std::mem::take(&mut *self.supervisors.lock().await);
+        let running = supervisors.iter().filter(|supervisor| !supervisor.handle.is_finished()).count();
+        if running > 0 {
+            println!("{} Stopping {} service(s)...", "■".blue(), running);
+        }
+
+        let _ = self.signal.set(signal);
+        self.stop.cancel();
+
+        let mut outcomes = HashMap::new();
+        for Supervisor { name, handle } in supervisors {
+            let outcome = handle.await.unwrap_or_default();
+            if let Some(status) = outcome.exited {
+                eprintln!("  {} service '{}' had already exited ({})", "⚠".yellow(), name, status);
+            }
+            outcomes.insert(name, outcome);
+        }
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_readiness_probes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ready_when = ReadyWhen {
+            port: Some(port),
+            host: Some("127.0.0.1".to_string()),
+            ..Default::default()
+        };
+        let probe = ReadinessProbe::new(&ready_when, Path::new(".")).unwrap();
+        assert!(probe.check(false).await);
+        drop(listener);
+        assert!(!probe.check(false).await);
+
+        let ready_when = ReadyWhen { log: Some("listening on \\d+".to_string()), ..Default::default() };
+        let probe = ReadinessProbe::new(&ready_when, Path::new(".")).unwrap();
+        assert!(!probe.check(false).await);
+        assert!(probe.check(true).await);
+
+        let ready_when = ReadyWhen { file: Some("Cargo.toml".to_string()), ..Default::default() };
+        let probe = ReadinessProbe::new(&ready_when, Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert!(probe.check(false).await);
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        assert_eq!(restart_delay(1), Duration::from_secs(1));
+        assert_eq!(restart_delay(3), Duration::from_secs(4));
+        assert_eq!(restart_delay(10), MAX_RESTART_DELAY);
+    }
+
+    #[test]
+    fn test_restart_limit() {
+        let restart = Restart { policy: RestartPolicy::OnFailure, max_restarts: Some(2) };
+        assert!(restart.allows(false, 1));
+        assert!(!restart.allows(false, 2));
+        assert!(!restart.allows(true, 0));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::utils::{get_shell_args, get_shell_command, parse_command};
+
+/// Flags that tell an interpreter to treat the next argument as a script
+const SCRIPT_FLAGS: [&str; 4] = ["-c", "-e", "/C", "-Command"];
+
+/// How a command string is turned into a process invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shell {
+    /// Split the command on whitespace and execute the program directly
+    Exec,
+    /// Pass the whole command string to an interpreter
+    Interpreter {
+        program: String,
+        args: Vec<String>,
+    },
+}
+
+impl Shell {
+    /// Parse a shell specification such as `exec`, `zsh`, `bash -euo pipefail` or `python3 -c`.
+    ///
+    /// If the specification does not already end with a script flag, `-c`
+    /// (or `/C` for `cmd`) is appended.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err("Shell cannot be empty".to_string());
+        }
+
+        if spec == "exec" {
+            return Ok(Shell::Exec);
+        }
+
+        let (program, mut args) = parse_command(spec);
+        let has_script_flag = args.last()
+            .is_some_and(|arg| SCRIPT_FLAGS.contains(&arg.as_str()));
+
+        if !has_script_flag {
+            let flag = if program.eq_ignore_ascii_case("cmd") { "/C" } else { "-c" };
+            args.push(flag.to_string());
+        }
+
+        Ok(Shell::Interpreter { program, args })
+    }
+
+    /// Resolve a task's shell: task-level override, then global setting, then the platform shell
+    pub fn resolve(task_shell: Option<&str>, global_shell: Option<&str>) -> Result<Self, String> {
+        match task_shell.or(global_shell) {
+            Some(spec) => Self::parse(spec),
+            None => Ok(Self::platform_default()),
+        }
+    }
+
+    /// The platform's default shell (`sh -c` or `cmd /C`)
+    pub fn platform_default() -> Self {
+        Shell::Interpreter {
+            program: get_shell_command(),
+            args: get_shell_args(),
+        }
+    }
+
+    /// Build the program and arguments used to run `command`
+    pub fn build(&self, command: &str) -> (String, Vec<String>) {
+        match self {
+            Shell::Exec => parse_command(command),
+            Shell::Interpreter { program, args } => {
+                let mut args = args.clone();
+                args.push(command.to_string());
+                (program.clone(), args)
+            }
+        }
+    }
+
+    /// Quote `arg` so that it reaches the command as a single argument
+    /// (`exec` commands are split on whitespace, so their arguments are left as they are)
+    pub fn quote(&self, arg: &str) -> String {
+        let plain = !arg.is_empty() && arg.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+
+        match self {
+            _ if plain => arg.to_string(),
+            Shell::Exec => arg.to_string(),
+            Shell::Interpreter { program, .. } if program.eq_ignore_ascii_case("cmd") => {
+                format!("\"{}\"", arg.replace('"', "\"\""))
+            }
+            Shell::Interpreter { .. } => format!("'{}'", arg.replace('\'', "'\\''")),
+        }
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shell::Exec => write!(f, "exec"),
+            Shell::Interpreter { program, args } => write!(f, "{} {}", program, args.join(" ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exec() {
+        assert_eq!(Shell::parse("exec").unwrap(), Shell::Exec);
+        assert_eq!(
+            Shell::Exec.build("echo a b"),
+            ("echo".to_string(), vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_appends_script_flag() {
+        let shell = Shell::parse("bash -euo pipefail").unwrap();
+        let (program, args) = shell.build("echo \"a b\" | wc -c");
+        assert_eq!(program, "bash");
+        assert_eq!(args, vec!["-euo", "pipefail", "-c", "echo \"a b\" | wc -c"]);
+
+        let shell = Shell::parse("zsh").unwrap();
+        assert_eq!(shell.build("ls").1, vec!["-c", "ls"]);
+    }
+
+    #[test]
+    fn test_parse_keeps_explicit_script_flag() {
+        let shell = Shell::parse("python3 -c").unwrap();
+        assert_eq!(shell.build("print(1)").1, vec!["-c", "print(1)"]);
+
+        let shell = Shell::parse("node -e").unwrap();
+        assert_eq!(shell.build("1").1, vec!["-e", "1"]);
+    }
+
+    #[test]
+    fn test_quote() {
+        let shell = Shell::parse("sh").unwrap();
+        assert_eq!(shell.quote("--nocapture"), "--nocapture");
+        assert_eq!(shell.quote("a b"), "'a b'");
+        assert_eq!(shell.quote("it's"), "'it'\\''s'");
+        assert_eq!(shell.quote(""), "''");
+        assert_eq!(Shell::parse("cmd").unwrap().quote("a b"), "\"a b\"");
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert!(Shell::parse("  ").is_err());
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        assert_eq!(Shell::resolve(Some("exec"), Some("zsh")).unwrap(), Shell::Exec);
+        assert_eq!(Shell::resolve(None, Some("exec")).unwrap(), Shell::Exec);
+        assert_eq!(Shell::resolve(None, None).unwrap(), Shell::platform_default());
+    }
+}
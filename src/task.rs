@@ -1,5 +1,12 @@
+use crate::dotenv::EnvFile;
+use crate::shell::Shell;
+use crate::template::Template;
+use crate::utils::random_fraction;
+use crate::vars::Var;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
@@ -7,7 +14,7 @@ pub struct Task {
     pub description: Option<String>,
     
     /// Commands to execute
-    pub commands: Vec<String>,
+    pub commands: Vec<TaskCommand>,
     
     /// Task dependencies (other task names)
     #[serde(default)]
@@ -17,6 +24,18 @@ pub struct Task {
     #[serde(default)]
     pub env: HashMap<String, String>,
     
+    /// Dotenv files for this task, loaded in order before `env`
+    #[serde(default)]
+    pub env_files: Vec<EnvFile>,
+    
+    /// Named parameters, referenced as `{{ params.name }}` in commands, env and working_dir
+    #[serde(default)]
+    pub params: HashMap<String, Param>,
+    
+    /// Template variables for this task (override global vars, not exported to commands)
+    #[serde(default)]
+    pub vars: HashMap<String, Var>,
+    
     /// Whether to run commands in parallel
     #[serde(default)]
     pub parallel: bool,
@@ -28,9 +47,57 @@ pub struct Task {
     /// Working directory for task execution
     pub working_dir: Option<String>,
     
+    /// Shell used to run commands (overrides the global shell, `exec` runs commands directly)
+    pub shell: Option<String>,
+    
     /// Timeout in seconds (None = no timeout)
     pub timeout: Option<u64>,
     
+    /// Time to wait after SIGTERM before killing a timed out command (in seconds)
+    pub kill_grace_period: Option<u64>,
+    
+    /// Glob patterns of files the task reads; unchanged sources let the task be skipped
+    #[serde(default)]
+    pub sources: Vec<String>,
+    
+    /// Glob patterns of files the task produces
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    
+    /// Store outputs and logs in the cache, restoring them instead of running when inputs match
+    #[serde(default)]
+    pub cache: bool,
+    
+    /// Commands whose output identifies the tools a cached task uses (e.g. `node --version`)
+    #[serde(default)]
+    pub tool_versions: Vec<String>,
+    
+    /// Named resource locks held while the task runs
+    #[serde(default)]
+    pub locks: Vec<String>,
+    
+    /// Number of job slots each of this task's commands occupies while running
+    pub weight: Option<u32>,
+    
+    /// Retry settings for failing commands
+    #[serde(flatten)]
+    pub retry: RetryPolicy,
+    
+    /// Long-running task (e.g. a server) that dependents start alongside once it is ready
+    #[serde(default)]
+    pub service: bool,
+    
+    /// Condition that marks a service as ready (ready as soon as it starts by default)
+    pub ready_when: Option<ReadyWhen>,
+    
+    /// When to restart a service, or a command of a parallel task, that exits
+    /// (`never`, `on-failure` or `always`)
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    
+    /// Most times a service or each command is restarted (unlimited by default)
+    pub max_restarts: Option<u32>,
+    
     /// Whether to continue on error
     #[serde(default)]
     pub continue_on_error: bool,
@@ -38,21 +105,53 @@ pub struct Task {
     /// Task is hidden from list (for internal tasks)
     #[serde(default)]
     pub hidden: bool,
+    
+    /// Directory of the configuration file defining the task, which its
+    /// relative `working_dir` and `env_files` are resolved against
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+    
+    /// Whether the task comes from the user configuration
+    #[serde(skip)]
+    pub from_user_config: bool,
+    
+    /// The configuration in a parent directory the task is inherited from, if any
+    #[serde(skip)]
+    pub inherited_from: Option<PathBuf>,
 }
 
 impl Task {
     pub fn new(commands: Vec<String>) -> Self {
         Self {
             description: None,
-            commands,
+            commands: commands.into_iter().map(TaskCommand::from).collect(),
             dependencies: Vec::new(),
             env: HashMap::new(),
+            env_files: Vec::new(),
+            params: HashMap::new(),
+            vars: HashMap::new(),
             parallel: false,
             sequential: false,
             working_dir: None,
+            shell: None,
             timeout: None,
+            kill_grace_period: None,
+            sources: Vec::new(),
+            outputs: Vec::new(),
+            cache: false,
+            tool_versions: Vec::new(),
+            locks: Vec::new(),
+            weight: None,
+            retry: RetryPolicy::default(),
+            service: false,
+            ready_when: None,
+            restart: RestartPolicy::Never,
+            max_restarts: None,
             continue_on_error: false,
             hidden: false,
+            base_dir: PathBuf::new(),
+            from_user_config: false,
+            inherited_from: None,
         }
     }
     
@@ -83,11 +182,58 @@ impl Task {
         self
     }
     
+    pub fn with_shell(mut self, shell: String) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+    
     pub fn with_timeout(mut self, timeout: u64) -> Self {
         self.timeout = Some(timeout);
         self
     }
     
+    pub fn with_sources(mut self, sources: Vec<String>) -> Self {
+        self.sources = sources;
+        self
+    }
+    
+    pub fn with_outputs(mut self, outputs: Vec<String>) -> Self {
+        self.outputs = outputs;
+        self
+    }
+    
+    pub fn cached(mut self) -> Self {
+        self.cache = true;
+        self
+    }
+    
+    pub fn with_locks(mut self, locks: Vec<String>) -> Self {
+        self.locks = locks;
+        self
+    }
+    
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+    
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retry.retries = Some(retries);
+        self
+    }
+    
+    pub fn service(mut self, ready_when: Option<ReadyWhen>) -> Self {
+        self.service = true;
+        self.ready_when = ready_when;
+        self
+    }
+    
+    pub fn with_restart(mut self, restart: RestartPolicy, max_restarts: Option<u32>) -> Self {
+        self.restart = restart;
+        self.max_restarts = max_restarts;
+        self
+    }
+    
     pub fn continue_on_error(mut self) -> Self {
         self.continue_on_error = true;
         self
@@ -109,6 +255,13 @@ impl Task {
         }
     }
     
+    /// The strings in which templates are rendered: commands, env values and working_dir
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        self.commands.iter().map(|command| command.run())
+            .chain(self.env.values().map(String::as_str))
+            .chain(self.working_dir.as_deref())
+    }
+    
     /// Validate task configuration
     pub fn validate(&self, name: &str) -> Result<(), String> {
         // Allow empty commands if task has dependencies (orchestrator tasks)
@@ -120,10 +273,334 @@ impl Task {
             return Err(format!("Task '{}' cannot be both parallel and sequential", name));
         }
         
+        for (param_name, param) in &self.params {
+            if !crate::params::is_valid_param_name(param_name) {
+                return Err(format!("Task '{}' has an invalid parameter name '{}'", name, param_name));
+            }
+            if let Some(default) = &param.default {
+                if !param.choices.is_empty() && !param.choices.contains(default) {
+                    return Err(format!(
+                        "Default '{}' of parameter '{}' of task '{}' is not one of its choices",
+                        default, param_name, name
+                    ));
+                }
+            }
+        }
+        
+        if self.vars.contains_key("params") {
+            return Err(format!("Task '{}' cannot have a variable named 'params'", name));
+        }
+        
+        for template in self.templates() {
+            let template = Template::parse(template).map_err(|e| format!("Task '{}': {}", name, e))?;
+            let unknown = template.references()
+                .filter(|reference| reference.path[0] == "params" && !reference.has_default)
+                .find_map(|reference| match reference.path.get(1) {
+                    Some(param) if self.params.contains_key(param) => None,
+                    Some(param) => Some(param.clone()),
+                    None => Some(String::new()),
+                });
+            if let Some(param) = unknown {
+                return Err(format!("Task '{}' uses undeclared parameter '{}'", name, param));
+            }
+        }
+        
+        for pattern in self.sources.iter().chain(&self.outputs) {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Task '{}' has invalid glob '{}': {}", name, pattern, e))?;
+        }
+        
+        if self.cache && (self.sources.is_empty() || self.outputs.is_empty()) {
+            return Err(format!("Task '{}' must declare sources and outputs to be cached", name));
+        }
+        
+        if self.locks.iter().any(|lock| lock.trim().is_empty()) {
+            return Err(format!("Task '{}' has an empty lock name", name));
+        }
+        
+        if self.weight == Some(0) {
+            return Err(format!("Task '{}' must have a weight of at least 1", name));
+        }
+        
+        if let Some(shell) = &self.shell {
+            Shell::parse(shell).map_err(|e| format!("Task '{}': {}", name, e))?;
+        }
+        
+        if self.service {
+            if self.commands.len() != 1 {
+                return Err(format!("Service '{}' must have exactly one command", name));
+            }
+            if self.cache || !self.sources.is_empty() {
+                return Err(format!("Service '{}' cannot have sources or be cached", name));
+            }
+            if self.max_restarts.is_some() && self.restart == RestartPolicy::Never {
+                return Err(format!("Service '{}' has max_restarts but is never restarted", name));
+            }
+        } else if self.ready_when.is_some() {
+            return Err(format!("Task '{}' has ready_when but is not a service", name));
+        } else if (self.restart != RestartPolicy::Never || self.max_restarts.is_some()) && !self.parallel {
+            // Sequential commands that restart would hold up the ones after them
+            return Err(format!("Task '{}' has a restart policy but is neither a service nor parallel", name));
+        } else if self.max_restarts.is_some() && self.restart == RestartPolicy::Never {
+            return Err(format!("Task '{}' has max_restarts but is never restarted", name));
+        }
+        
+        if let Some(ready_when) = &self.ready_when {
+            ready_when.validate().map_err(|e| format!("Service '{}': {}", name, e))?;
+        }
+        
         Ok(())
     }
 }
 
+/// Condition that marks a service as ready; exactly one of `port`, `http`,
+/// `log` and `file` must be set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadyWhen {
+    /// A TCP port accepts connections
+    pub port: Option<u16>,
+    
+    /// Host to connect to for `port` (default: localhost)
+    pub host: Option<String>,
+    
+    /// A URL responds with HTTP 200
+    pub http: Option<String>,
+    
+    /// A line of the service's output matches this regular expression
+    pub log: Option<String>,
+    
+    /// A file exists (relative to the working directory)
+    pub file: Option<String>,
+    
+    /// Seconds to wait for the service to become ready (default: 60)
+    pub timeout: Option<u64>,
+}
+
+impl ReadyWhen {
+    pub fn validate(&self) -> Result<(), String> {
+        let probes = [
+            self.port.is_some(),
+            self.http.is_some(),
+            self.log.is_some(),
+            self.file.is_some(),
+        ];
+        if probes.iter().filter(|set| **set).count() != 1 {
+            return Err("ready_when needs exactly one of port, http, log or file".to_string());
+        }
+        
+        if self.host.is_some() && self.port.is_none() {
+            return Err("ready_when.host is only used with port".to_string());
+        }
+        
+        if let Some(pattern) = &self.log {
+            regex::Regex::new(pattern)
+                .map_err(|e| format!("invalid ready_when.log pattern: {}", e))?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// A named parameter of a task, set on the command line with `task:name=value`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Param {
+    /// What the parameter is for
+    pub description: Option<String>,
+    
+    /// Value used when none is given (the parameter is required without one)
+    pub default: Option<String>,
+    
+    /// Allowed values (any value is allowed when empty)
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+/// When a service or a command of a parallel task that exits is restarted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave the service stopped
+    #[default]
+    Never,
+    /// Restart the service when it exits with an error or is killed by a signal
+    OnFailure,
+    /// Restart the service whenever it exits
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether a service that exited, successfully or not, is restarted
+    pub fn restarts(&self, success: bool) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+impl std::fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "never"),
+            RestartPolicy::OnFailure => write!(f, "on-failure"),
+            RestartPolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+/// A command to execute, written either as a plain string or as a map with
+/// per-command settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TaskCommand {
+    Simple(String),
+    Detailed(CommandDetails),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandDetails {
+    /// Command line to run
+    pub run: String,
+    
+    /// Timeout in seconds for this command (overrides the task timeout)
+    pub timeout: Option<u64>,
+    
+    /// Retry settings for this command (override the task's settings)
+    #[serde(flatten)]
+    pub retry: RetryPolicy,
+}
+
+impl TaskCommand {
+    /// The command line to run
+    pub fn run(&self) -> &str {
+        match self {
+            TaskCommand::Simple(run) => run,
+            TaskCommand::Detailed(details) => &details.run,
+        }
+    }
+    
+    /// Replace the command line to run
+    pub fn set_run(&mut self, run: String) {
+        match self {
+            TaskCommand::Simple(command) => *command = run,
+            TaskCommand::Detailed(details) => details.run = run,
+        }
+    }
+    
+    /// Timeout for this command, if set
+    pub fn timeout(&self) -> Option<u64> {
+        match self {
+            TaskCommand::Simple(_) => None,
+            TaskCommand::Detailed(details) => details.timeout,
+        }
+    }
+    
+    /// Retry settings for this command, falling back to the task's settings
+    pub fn retry_policy(&self, task_policy: &RetryPolicy) -> RetryPolicy {
+        match self {
+            TaskCommand::Simple(_) => task_policy.clone(),
+            TaskCommand::Detailed(details) => details.retry.or(task_policy),
+        }
+    }
+}
+
+impl From<String> for TaskCommand {
+    fn from(run: String) -> Self {
+        TaskCommand::Simple(run)
+    }
+}
+
+impl From<&str> for TaskCommand {
+    fn from(run: &str) -> Self {
+        TaskCommand::Simple(run.to_string())
+    }
+}
+
+impl PartialEq<String> for TaskCommand {
+    fn eq(&self, other: &String) -> bool {
+        self.run() == other
+    }
+}
+
+impl std::fmt::Display for TaskCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.run())
+    }
+}
+
+/// How long to wait between retries of a failing command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryBackoff {
+    /// Wait `retry_delay` before every retry
+    #[default]
+    Fixed,
+    /// Double the delay after every retry
+    Exponential,
+}
+
+/// Retry settings, used at task level and per command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Number of times to retry a failing command
+    pub retries: Option<u32>,
+    
+    /// Delay before the first retry in seconds (default: 1)
+    pub retry_delay: Option<u64>,
+    
+    /// How the delay grows between retries (`fixed` or `exponential`)
+    pub retry_backoff: Option<RetryBackoff>,
+    
+    /// Randomize each delay to between half and all of its value
+    pub retry_jitter: Option<bool>,
+}
+
+impl RetryPolicy {
+    /// Longest delay between two attempts
+    const MAX_DELAY: Duration = Duration::from_secs(300);
+    
+    /// Fill unset fields from `fallback`
+    pub fn or(&self, fallback: &RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries.or(fallback.retries),
+            retry_delay: self.retry_delay.or(fallback.retry_delay),
+            retry_backoff: self.retry_backoff.or(fallback.retry_backoff),
+            retry_jitter: self.retry_jitter.or(fallback.retry_jitter),
+        }
+    }
+    
+    /// Total number of attempts, including the first one
+    pub fn max_attempts(&self) -> u32 {
+        self.retries.unwrap_or(0).saturating_add(1)
+    }
+    
+    /// Delay before the given retry (1 for the first retry), without jitter
+    pub fn base_delay(&self, retry: u32) -> Duration {
+        let delay = Duration::from_secs(self.retry_delay.unwrap_or(1));
+        
+        let delay = match self.retry_backoff.unwrap_or_default() {
+            RetryBackoff::Fixed => delay,
+            RetryBackoff::Exponential => {
+                delay.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            }
+        };
+        
+        delay.min(Self::MAX_DELAY)
+    }
+    
+    /// Delay before the given retry (1 for the first retry)
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self.base_delay(retry);
+        
+        if self.retry_jitter.unwrap_or(false) {
+            delay.mul_f64(0.5 + random_fraction() / 2.0)
+        } else {
+            delay
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExecutionMode {
     /// Automatically determine based on task configuration
@@ -150,7 +627,13 @@ mod tests {
         assert!(!task.parallel);
         assert!(!task.sequential);
         assert_eq!(task.working_dir, None);
+        assert_eq!(task.shell, None);
         assert_eq!(task.timeout, None);
+        assert_eq!(task.kill_grace_period, None);
+        assert_eq!(task.sources.len(), 0);
+        assert_eq!(task.outputs.len(), 0);
+        assert_eq!(task.locks.len(), 0);
+        assert_eq!(task.weight, None);
         assert!(!task.continue_on_error);
         assert!(!task.hidden);
     }
@@ -211,6 +694,78 @@ mod tests {
         task.parallel = true;
         task.sequential = true;
         assert!(task.validate("test").is_err());
+        
+        // Task with a zero weight
+        let task = Task::new(vec!["echo hello".to_string()]).with_weight(0);
+        assert!(task.validate("test").is_err());
+        
+        // Task with an empty shell
+        let task = Task::new(vec!["echo hello".to_string()])
+            .with_shell(" ".to_string());
+        assert!(task.validate("test").is_err());
+
+        // Cached task without outputs
+        let task = Task::new(vec!["make".to_string()])
+            .with_sources(vec!["src/*.c".to_string()])
+            .cached();
+        assert!(task.validate("test").is_err());
+        assert!(task.with_outputs(vec!["build/*".to_string()]).validate("test").is_ok());
+
+        // Services need exactly one readiness probe
+        let ready_when = ReadyWhen { port: Some(8080), ..Default::default() };
+        let task = Task::new(vec!["npm start".to_string()]).service(Some(ready_when.clone()));
+        assert!(task.validate("test").is_ok());
+        let both = ReadyWhen { file: Some("ready".to_string()), ..ready_when.clone() };
+        assert!(Task::new(vec!["npm start".to_string()]).service(Some(both)).validate("test").is_err());
+        let mut task = Task::new(vec!["npm start".to_string()]);
+        task.ready_when = Some(ready_when);
+        assert!(task.validate("test").is_err());
+
+        // Only services are restarted, and max_restarts needs a restart policy
+        let task = Task::new(vec!["npm start".to_string()])
+            .with_restart(RestartPolicy::OnFailure, Some(3));
+        assert!(task.validate("test").is_err());
+        assert!(task.service(None).validate("test").is_ok());
+        let task = Task::new(vec!["npm start".to_string()])
+            .service(None)
+            .with_restart(RestartPolicy::Never, Some(3));
+        assert!(task.validate("test").is_err());
+
+        // Parameters must be declared, and defaults must be among the choices
+        let mut task = Task::new(vec!["cargo build --profile {{ params.profile }}".to_string()]);
+        assert!(task.validate("test").is_err());
+        task.params.insert("profile".to_string(), Param {
+            default: Some("dev".to_string()),
+            choices: vec!["release".to_string()],
+            ..Default::default()
+        });
+        assert!(task.validate("test").is_err());
+        task.params.get_mut("profile").unwrap().choices.push("dev".to_string());
+        assert!(task.validate("test").is_ok());
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        let task: Task = serde_yaml::from_str("commands: [\"npm start\"]\nservice: true\nrestart: on-failure\n").unwrap();
+        assert_eq!(task.restart, RestartPolicy::OnFailure);
+        assert!(task.restart.restarts(false));
+        assert!(!task.restart.restarts(true));
+        assert!(RestartPolicy::Always.restarts(true));
+        assert!(!RestartPolicy::Never.restarts(false));
+        
+        // Commands of parallel tasks are restarted on their own, sequential ones aren't
+        let mut task: Task = serde_yaml::from_str(r#"
+commands: ["cargo run", "cargo test --watch"]
+parallel: true
+restart: on-failure
+max_restarts: 3
+"#).unwrap();
+        assert!(task.validate("backend").is_ok());
+        task.parallel = false;
+        assert!(task.validate("backend").is_err());
+        task.parallel = true;
+        task.restart = RestartPolicy::Never;
+        assert!(task.validate("backend").is_err());
     }
 
     #[test]
@@ -229,4 +784,54 @@ mod tests {
         assert_eq!(task.dependencies, deserialized.dependencies);
         assert_eq!(task.parallel, deserialized.parallel);
     }
+
+    #[test]
+    fn test_command_forms() {
+        let yaml = r#"
+commands:
+  - "echo simple"
+  - run: "sleep 10"
+    timeout: 5
+"#;
+        let task: Task = serde_yaml::from_str(yaml).unwrap();
+        
+        assert_eq!(task.commands[0].run(), "echo simple");
+        assert_eq!(task.commands[0].timeout(), None);
+        assert_eq!(task.commands[1].run(), "sleep 10");
+        assert_eq!(task.commands[1].timeout(), Some(5));
+        
+        // Plain commands serialize back to plain strings
+        let json = serde_json::to_string(&task.commands[0]).unwrap();
+        assert_eq!(json, "\"echo simple\"");
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let yaml = r#"
+commands:
+  - "npm test"
+  - run: "npm run e2e"
+    retries: 5
+retries: 2
+retry_delay: 3
+retry_backoff: exponential
+"#;
+        let task: Task = serde_yaml::from_str(yaml).unwrap();
+        
+        assert_eq!(task.retry.max_attempts(), 3);
+        assert_eq!(task.retry.base_delay(1), Duration::from_secs(3));
+        assert_eq!(task.retry.base_delay(3), Duration::from_secs(12));
+        
+        // Per-command settings override the task's and inherit the rest
+        let policy = task.commands[1].retry_policy(&task.retry);
+        assert_eq!(policy.max_attempts(), 6);
+        assert_eq!(policy.retry_backoff, Some(RetryBackoff::Exponential));
+        assert_eq!(task.commands[0].retry_policy(&task.retry), task.retry);
+        
+        // Fixed backoff is the default, and delays are capped
+        let policy = RetryPolicy { retry_delay: Some(200), ..Default::default() };
+        assert_eq!(policy.base_delay(4), Duration::from_secs(200));
+        let policy = RetryPolicy { retry_backoff: Some(RetryBackoff::Exponential), ..policy };
+        assert_eq!(policy.base_delay(4), Duration::from_secs(300));
+    }
 } 
\ No newline at end of file
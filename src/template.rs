@@ -0,0 +1,374 @@
+use crate::error::TaskRunnerError;
+use serde_json::Value;
+
+/// A string with `{{ expression }}` placeholders, such as
+/// `{{ targets | join(",") }}` or `{{ params.profile | default("dev") | upper }}`.
+/// `{{{{` is a literal `{{`, e.g. for `docker ps --format '{{{{.Names}}'`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Expr(Expr),
+}
+
+/// A value followed by any number of filters
+#[derive(Debug, Clone, PartialEq)]
+struct Expr {
+    operand: Operand,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// A variable, optionally followed by map keys and list indices (`deploy.hosts.0`)
+    Path(Vec<String>),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// Use the argument when the value is undefined, null or empty
+    Default(Value),
+    Upper,
+    /// Join the items of a list with the separator (`,` by default)
+    Join(String),
+    Replace(String, String),
+}
+
+/// A variable used by a template
+#[derive(Debug, Clone, Copy)]
+pub struct Reference<'a> {
+    /// The variable followed by map keys and list indices
+    pub path: &'a [String],
+    /// Whether a `default` filter makes up for the variable being undefined
+    pub has_default: bool,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self, TaskRunnerError> {
+        let error = |reason: String| {
+            TaskRunnerError::TemplateError(format!("invalid template '{}': {}", text, reason))
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            literal.push_str(&rest[..start]);
+            if let Some(after) = rest[start..].strip_prefix("{{{{") {
+                literal.push_str("{{");
+                rest = after;
+                continue;
+            }
+            if !literal.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut literal)));
+            }
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| error("unclosed '{{'".to_string()))?;
+            parts.push(Part::Expr(Parser::new(&after[..end]).expr().map_err(error)?));
+            rest = &after[end + 2..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// The variables the template uses
+    pub fn references(&self) -> impl Iterator<Item = Reference<'_>> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Expr(Expr { operand: Operand::Path(path), filters }) => Some(Reference {
+                path,
+                has_default: filters.iter().any(|filter| matches!(filter, Filter::Default(_))),
+            }),
+            _ => None,
+        })
+    }
+
+    /// Render the template, looking variables up by name with `lookup`.
+    ///
+    /// Using a variable that `lookup` doesn't know, without a `default`
+    /// filter, is an error.
+    pub fn render<F>(&self, mut lookup: F) -> Result<String, TaskRunnerError>
+    where
+        F: FnMut(&str) -> Result<Option<Value>, TaskRunnerError>,
+    {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Expr(expr) => {
+                    let mut value = match &expr.operand {
+                        Operand::Literal(value) => Some(value.clone()),
+                        Operand::Path(path) => lookup(&path[0])?
+                            .and_then(|root| path[1..].iter().try_fold(root, get)),
+                    };
+                    for filter in &expr.filters {
+                        value = Some(filter.apply(value));
+                    }
+
+                    match value {
+                        Some(value) => output.push_str(&to_text(&value)),
+                        None => {
+                            let Operand::Path(path) = &expr.operand else { unreachable!() };
+                            return Err(TaskRunnerError::TemplateError(
+                                format!("'{}' is not defined", path.join("."))
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Filter {
+    fn apply(&self, value: Option<Value>) -> Value {
+        match self {
+            Filter::Default(fallback) => match value {
+                None | Some(Value::Null) => fallback.clone(),
+                Some(Value::String(s)) if s.is_empty() => fallback.clone(),
+                Some(value) => value,
+            },
+            Filter::Upper => Value::String(to_text(&value.unwrap_or_default()).to_uppercase()),
+            Filter::Join(separator) => match value {
+                Some(Value::Array(items)) => {
+                    let items: Vec<String> = items.iter().map(to_text).collect();
+                    Value::String(items.join(separator))
+                }
+                value => value.unwrap_or_default(),
+            },
+            Filter::Replace(from, to) => {
+                Value::String(to_text(&value.unwrap_or_default()).replace(from.as_str(), to))
+            }
+        }
+    }
+}
+
+/// Look up a map key or list index of a value
+fn get(value: Value, key: &String) -> Option<Value> {
+    match value {
+        Value::Object(mut map) => map.remove(key),
+        Value::Array(mut items) => {
+            let index: usize = key.parse().ok()?;
+            (index < items.len()).then(|| items.swap_remove(index))
+        }
+        _ => None,
+    }
+}
+
+/// How a value is written into a template: lists as their items separated by
+/// spaces, maps as JSON, null as nothing
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(to_text).collect::<Vec<_>>().join(" "),
+        value => value.to_string(),
+    }
+}
+
+/// Parser for the expression between `{{` and `}}`
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let operand = self.operand()?;
+        let mut filters = Vec::new();
+        while self.eat('|') {
+            filters.push(self.filter()?);
+        }
+
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(format!("unexpected '{}'", &self.input[self.pos..]));
+        }
+        Ok(Expr { operand, filters })
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut path = vec![self.word()];
+                while self.peek() == Some('.') {
+                    self.pos += 1;
+                    let key = self.word();
+                    if key.is_empty() {
+                        return Err("expected a name after '.'".to_string());
+                    }
+                    path.push(key);
+                }
+                match path.as_slice() {
+                    [word] if word == "true" => Ok(Operand::Literal(Value::Bool(true))),
+                    [word] if word == "false" => Ok(Operand::Literal(Value::Bool(false))),
+                    [word] if word == "null" => Ok(Operand::Literal(Value::Null)),
+                    _ => Ok(Operand::Path(path)),
+                }
+            }
+            Some(_) => self.literal().map(Operand::Literal),
+            None => Err("empty expression".to_string()),
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter, String> {
+        self.skip_whitespace();
+        let name = self.word();
+
+        let mut args = Vec::new();
+        if self.eat('(') && !self.eat(')') {
+            loop {
+                args.push(self.literal()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(format!("expected ',' or ')' in arguments of '{}'", name));
+                }
+            }
+        }
+
+        let string = |value: &Value| to_text(value);
+        match (name.as_str(), args.as_slice()) {
+            ("default", [fallback]) => Ok(Filter::Default(fallback.clone())),
+            ("upper", []) => Ok(Filter::Upper),
+            ("join", []) => Ok(Filter::Join(",".to_string())),
+            ("join", [separator]) => Ok(Filter::Join(string(separator))),
+            ("replace", [from, to]) => Ok(Filter::Replace(string(from), string(to))),
+            ("default" | "upper" | "join" | "replace", _) => {
+                Err(format!("wrong number of arguments for '{}'", name))
+            }
+            ("", _) => Err("expected a filter after '|'".to_string()),
+            _ => Err(format!("unknown filter '{}'", name)),
+        }
+    }
+
+    /// A quoted string, number, boolean or null
+    fn literal(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                let rest = &self.input[self.pos..];
+                let end = rest.find(quote).ok_or("unterminated string")?;
+                self.pos += end + 1;
+                Ok(Value::String(rest[..end].to_string()))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+                serde_json::from_str::<serde_json::Number>(&number)
+                    .map(Value::Number)
+                    .map_err(|_| format!("invalid number '{}'", number))
+            }
+            Some(_) => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    _ => Err(format!("expected a value, found '{}'", word)),
+                }
+            }
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    /// A run of name characters, which also covers list indices
+    fn word(&mut self) -> String {
+        self.take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+    }
+
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c: char| !accept(c)).unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len].to_string()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(text: &str, vars: Value) -> Result<String, TaskRunnerError> {
+        Template::parse(text)?.render(|name| Ok(vars.get(name).cloned()))
+    }
+
+    #[test]
+    fn test_render() {
+        let vars = json!({
+            "name": "my-app",
+            "port": 8080,
+            "targets": ["linux", "macos"],
+            "deploy": { "hosts": ["a.example.com", "b.example.com"] },
+            "empty": "",
+        });
+
+        assert_eq!(render("run {{ name }} on {{port}}", vars.clone()).unwrap(), "run my-app on 8080");
+        assert_eq!(render("{{ targets }}", vars.clone()).unwrap(), "linux macos");
+        assert_eq!(render("{{ targets | join(\",\") }}", vars.clone()).unwrap(), "linux,macos");
+        assert_eq!(render("{{ deploy.hosts.1 }}", vars.clone()).unwrap(), "b.example.com");
+        assert_eq!(render("{{ 1.5 }} {{ true }}", vars.clone()).unwrap(), "1.5 true");
+        assert_eq!(render("{{ name | replace('-', '_') | upper }}", vars.clone()).unwrap(), "MY_APP");
+        assert_eq!(render("{{ missing | default(3) }}", vars.clone()).unwrap(), "3");
+        assert_eq!(render("{{ empty | default('none') }}", vars.clone()).unwrap(), "none");
+        assert_eq!(render("{{ '{{' }} literal", vars.clone()).unwrap(), "{{ literal");
+        assert_eq!(
+            render("docker ps --format '{{{{.Names}}' --filter name={{ name }}", vars.clone()).unwrap(),
+            "docker ps --format '{{.Names}}' --filter name=my-app"
+        );
+        assert_eq!(render("{{{{ name }} }}", vars.clone()).unwrap(), "{{ name }} }}");
+        assert!(render("{{ missing }}", vars.clone()).is_err());
+        assert!(render("{{ deploy.port }}", vars).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{{ name").is_err());
+        assert!(Template::parse("{{ }}").is_err());
+        assert!(Template::parse("{{ name | lower }}").is_err());
+        assert!(Template::parse("{{ name | replace('a') }}").is_err());
+        assert!(Template::parse("{{ name extra }}").is_err());
+
+        let template = Template::parse("{{ params.profile }} {{ tag | default('latest') }}").unwrap();
+        let references: Vec<_> = template.references().collect();
+        assert_eq!(references[0].path, ["params", "profile"]);
+        assert!(!references[0].has_default);
+        assert!(references[1].has_default);
+    }
+}
@@ -1,20 +1,198 @@
+use crate::error::TaskRunnerError;
 use std::collections::HashMap;
 use std::env;
+use std::path::{Component, Path, PathBuf};
 
-/// Expand environment variables in a string
-pub fn expand_env_vars(s: &str, env_vars: &HashMap<String, String>) -> String {
-    let mut result = s.to_string();
-    
-    // Replace ${VAR} and $VAR patterns
-    for (key, value) in env_vars {
-        let var_pattern = format!("${{{}}}", key);
-        result = result.replace(&var_pattern, value);
-        
-        let simple_pattern = format!("${}", key);
-        result = result.replace(&simple_pattern, value);
+/// A piece of a string with environment variable references
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EnvSegment {
+    Text(String),
+    /// A reference, with the text it was written as
+    Ref(String, EnvRef),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EnvRef {
+    /// `$VAR` or `${VAR}`
+    Var(String),
+    /// `${VAR:-default}`: the default when the variable is unset or empty
+    VarOrDefault(String, Vec<EnvSegment>),
+    /// `${VAR:?message}`: an error when the variable is unset or empty
+    VarOrError(String, String),
+    /// Any other `${...}` form, such as `${VAR%.txt}` or `${#VAR}`, which only
+    /// a shell can expand
+    Other,
+}
+
+/// Split a string into text and environment variable references.
+///
+/// `$$` is a literal `$`, and a `$` not followed by a name or `{` (e.g. `$1`
+/// or `$?`) is kept as it is.
+fn parse_env_refs(s: &str) -> Result<Vec<EnvSegment>, TaskRunnerError> {
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = s;
+
+    while let Some(pos) = rest.find('$') {
+        text.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        if let Some(after) = after.strip_prefix('$') {
+            text.push('$');
+            rest = after;
+        } else if let Some(inner) = after.strip_prefix('{') {
+            // Find the matching brace; defaults may contain references of their own
+            let mut depth = 1;
+            let end = inner.char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(|| TaskRunnerError::EnvVarError(format!("unclosed '${{' in '{}'", s)))?;
+            let body = &inner[..end];
+
+            let name_len = body.find(|c: char| !is_name_char(c)).unwrap_or(body.len());
+            let (name, modifier) = body.split_at(name_len);
+
+            let reference = if !name.starts_with(is_name_start) {
+                EnvRef::Other
+            } else if modifier.is_empty() {
+                EnvRef::Var(name.to_string())
+            } else if let Some(default) = modifier.strip_prefix(":-") {
+                EnvRef::VarOrDefault(name.to_string(), parse_env_refs(default)?)
+            } else if let Some(message) = modifier.strip_prefix(":?") {
+                EnvRef::VarOrError(name.to_string(), message.to_string())
+            } else {
+                EnvRef::Other
+            };
+
+            if !text.is_empty() {
+                segments.push(EnvSegment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(EnvSegment::Ref(format!("${{{}}}", body), reference));
+            rest = &inner[end + 1..];
+        } else if after.starts_with(is_name_start) {
+            let name_len = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+            if !text.is_empty() {
+                segments.push(EnvSegment::Text(std::mem::take(&mut text)));
+            }
+            let name = &after[..name_len];
+            segments.push(EnvSegment::Ref(format!("${}", name), EnvRef::Var(name.to_string())));
+            rest = &after[name_len..];
+        } else {
+            text.push('$');
+            rest = after;
+        }
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(EnvSegment::Text(text));
+    }
+    Ok(segments)
+}
+
+/// Expand environment variables in a string: `${VAR}`, `$VAR`,
+/// `${VAR:-default}` and `${VAR:?message}`, with `$$` for a literal `$`.
+///
+/// Undefined variables expand to nothing, as in the shell. This is for
+/// strings no shell sees, such as paths; see `expand_declared_env_vars`
+/// for commands.
+pub fn expand_env_vars(s: &str, env_vars: &HashMap<String, String>) -> Result<String, TaskRunnerError> {
+    expand_segments(&parse_env_refs(s)?, env_vars, None)
+}
+
+/// Expand the variables of a command that runs through a shell, with `$$`
+/// for a literal `$`.
+///
+/// Only the variables in `declared` (those the configuration sets) are
+/// expanded. Other references, including shell variables such as a loop's
+/// `$f` and forms like `${VAR%.txt}`, are left for the shell. `${VAR:?message}`
+/// fails when `VAR` is not set in `env_vars`, the command's whole environment.
+pub fn expand_declared_env_vars(
+    s: &str,
+    declared: &HashMap<String, String>,
+    env_vars: &HashMap<String, String>,
+) -> Result<String, TaskRunnerError> {
+    expand_segments(&parse_env_refs(s)?, env_vars, Some(declared))
+}
+
+/// Expand `segments` with `env_vars`, or only the variables in `declared` if given
+fn expand_segments(
+    segments: &[EnvSegment],
+    env_vars: &HashMap<String, String>,
+    declared: Option<&HashMap<String, String>>,
+) -> Result<String, TaskRunnerError> {
+    let set = |name: &str| env_vars.get(name).filter(|value| !value.is_empty());
+    let expands = |name: &str| declared.is_none_or(|declared| declared.contains_key(name));
+
+    let mut result = String::new();
+    for segment in segments {
+        let (raw, reference) = match segment {
+            EnvSegment::Text(text) => {
+                result.push_str(text);
+                continue;
+            }
+            EnvSegment::Ref(raw, reference) => (raw, reference),
+        };
+        match reference {
+            EnvRef::Var(name) if expands(name) => {
+                result.push_str(env_vars.get(name).map(String::as_str).unwrap_or_default());
+            }
+            EnvRef::VarOrDefault(name, default) if expands(name) => match set(name) {
+                Some(value) => result.push_str(value),
+                None => result.push_str(&expand_segments(default, env_vars, declared)?),
+            },
+            EnvRef::VarOrError(name, message) => match set(name) {
+                Some(value) if expands(name) => result.push_str(value),
+                Some(_) => result.push_str(raw),
+                None if message.is_empty() => {
+                    return Err(TaskRunnerError::EnvVarError(format!("{} is not set", name)));
+                }
+                None => return Err(TaskRunnerError::EnvVarError(format!("{}: {}", name, message))),
+            },
+            EnvRef::Other if declared.is_none() => {
+                return Err(TaskRunnerError::EnvVarError(format!("unsupported expansion '{}'", raw)));
+            }
+            _ => result.push_str(raw),
+        }
+    }
+    Ok(result)
+}
+
+/// Names of the environment variables a string needs, i.e. those it uses
+/// without a default
+pub fn required_env_vars(s: &str) -> Result<Vec<String>, TaskRunnerError> {
+    Ok(parse_env_refs(s)?.into_iter()
+        .filter_map(|segment| match segment {
+            EnvSegment::Ref(_, EnvRef::Var(name) | EnvRef::VarOrError(name, _)) => Some(name),
+            _ => None,
+        })
+        .collect())
+}
+
+/// `path` resolved against `base`, leaving out `.` components, or `path`
+/// itself when `base` is empty
+pub fn join_path(base: &Path, path: &str) -> String {
+    if base.as_os_str().is_empty() {
+        return path.to_string();
+    }
+    let joined: PathBuf = base.join(path).components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect();
+    if joined.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        joined.to_string_lossy().into_owned()
     }
-    
-    result
 }
 
 /// Get the current working directory as a string
@@ -49,6 +227,15 @@ pub fn parse_command(command: &str) -> (String, Vec<String>) {
     (program, args)
 }
 
+/// Random number in `[0, 1)`, good enough for spreading out retries
+pub fn random_fraction() -> f64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
 /// Format duration for display
 pub fn format_duration(duration: std::time::Duration) -> String {
     let secs = duration.as_secs();
@@ -84,4 +271,50 @@ pub fn get_shell_args() -> Vec<String> {
     }
 }
 
- 
\ No newline at end of file
+ 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env_vars() {
+        let env = HashMap::from([
+            ("PATH".to_string(), "/bin".to_string()),
+            ("PATHX".to_string(), "/opt".to_string()),
+            ("EMPTY".to_string(), String::new()),
+        ]);
+        let expand = |s: &str| expand_env_vars(s, &env).unwrap();
+
+        assert_eq!(expand("$PATHX:$PATH ${PATH}/sh"), "/opt:/bin /bin/sh");
+        assert_eq!(expand("$PATH_SUFFIX|$UNSET|"), "||");
+        assert_eq!(expand("${UNSET:-${PATH:-x}/y} ${EMPTY:-none}"), "/bin/y none");
+        assert_eq!(expand("cost $$5 for $$PATH, $1 $? $"), "cost $5 for $PATH, $1 $? $");
+        assert_eq!(expand("${PATH:?required}"), "/bin");
+
+        let error = expand_env_vars("${UNSET:?must be set}", &env).unwrap_err();
+        assert_eq!(error.to_string(), "Environment variable error: UNSET: must be set");
+        assert!(expand_env_vars("${PATH", &env).is_err());
+        assert!(expand_env_vars("${PATH:0:2}", &env).is_err());
+    }
+
+    #[test]
+    fn test_expand_declared_env_vars() {
+        let declared = HashMap::from([("OUT".to_string(), "dist".to_string())]);
+        let mut env = declared.clone();
+        env.insert("HOME".to_string(), "/home/me".to_string());
+        let expand = |s: &str| expand_declared_env_vars(s, &declared, &env);
+
+        assert_eq!(expand("for f in a b; do echo \"$f in $OUT\"; done").unwrap(), "for f in a b; do echo \"$f in dist\"; done");
+        assert_eq!(expand("${OUT}/${HOME} ${UNSET:-x} ${OUT:-x}").unwrap(), "dist/${HOME} ${UNSET:-x} dist");
+        assert_eq!(expand("${F%.txt} ${#ARR} ${OUT:0:2} $${OUT}").unwrap(), "${F%.txt} ${#ARR} ${OUT:0:2} ${OUT}");
+        assert_eq!(expand("${HOME:?} ${OUT:?}").unwrap(), "${HOME:?} dist");
+        assert!(expand("${UNSET:?required}").is_err());
+    }
+
+    #[test]
+    fn test_required_env_vars() {
+        let required = required_env_vars("$A ${B} ${C:-$D} ${E:?x} $$F").unwrap();
+        assert_eq!(required, vec!["A", "B", "E"]);
+    }
+}
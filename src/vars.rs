@@ -0,0 +1,264 @@
+use crate::config::Config;
+use crate::dotenv;
+use crate::error::TaskRunnerError;
+use crate::shell::Shell;
+use crate::task::Task;
+use crate::template::Template;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// A template variable: a string, number, boolean, list or map, or a command
+/// whose output is the value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Var {
+    Command(CommandVar),
+    Value(Value),
+}
+
+/// A variable computed from the trimmed stdout of a command, e.g.
+/// `sh: git rev-parse --short HEAD`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandVar {
+    pub sh: String,
+}
+
+impl std::fmt::Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Var::Command(command) => write!(f, "sh: {}", command.sh),
+            Var::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The variables of a run. Command variables are only run when a task uses
+/// them, and at most once per run.
+pub struct RunVars<'a> {
+    config: &'a Config,
+    /// Values of command variables that have been run, by task (`None` for global variables)
+    computed: HashMap<(Option<String>, String), Value>,
+    /// Environment of variable commands, resolved when the first one runs
+    env: Option<HashMap<String, String>>,
+}
+
+impl<'a> RunVars<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            computed: HashMap::new(),
+            env: None,
+        }
+    }
+
+    /// Render the templates in a task's commands, env and working directory
+    /// using its parameter values, its own variables and the global variables
+    pub async fn render_task(
+        &mut self,
+        task_name: &str,
+        task: &mut Task,
+        params: &HashMap<String, String>,
+    ) -> Result<(), TaskRunnerError> {
+        let in_task = |e: TaskRunnerError| match e {
+            TaskRunnerError::TemplateError(message) => {
+                TaskRunnerError::TemplateError(format!("task '{}': {}", task_name, message))
+            }
+            e => e,
+        };
+
+        let texts = task.commands.iter().map(|command| command.run())
+            .chain(task.env.values().map(String::as_str))
+            .chain(task.working_dir.as_deref());
+        let templates = texts.map(Template::parse).collect::<Result<Vec<_>, _>>().map_err(in_task)?;
+
+        // Run the commands of the variables the templates use before rendering them
+        for reference in templates.iter().flat_map(Template::references) {
+            let name = &reference.path[0];
+            if let Some(var) = task.vars.get(name) {
+                self.compute(Some((task_name, task)), name, var).await.map_err(in_task)?;
+            } else if let Some(var) = self.config.vars.get(name) {
+                self.compute(None, name, var).await.map_err(in_task)?;
+            }
+        }
+
+        let params = Value::Object(params.iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect());
+        let lookup = |name: &str| -> Result<Option<Value>, TaskRunnerError> {
+            if name == "params" {
+                return Ok(Some(params.clone()));
+            }
+            let (scope, var) = match task.vars.get(name) {
+                Some(var) => (Some(task_name.to_string()), var),
+                None => match self.config.vars.get(name) {
+                    Some(var) => (None, var),
+                    None => return Ok(None),
+                },
+            };
+            Ok(match var {
+                Var::Value(value) => Some(value.clone()),
+                Var::Command(_) => self.computed.get(&(scope, name.to_string())).cloned(),
+            })
+        };
+        let mut rendered = templates.iter()
+            .map(|template| template.render(lookup))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(in_task)?
+            .into_iter();
+
+        for command in &mut task.commands {
+            command.set_run(rendered.next().unwrap());
+        }
+        for value in task.env.values_mut() {
+            *value = rendered.next().unwrap();
+        }
+        if let Some(dir) = &mut task.working_dir {
+            *dir = rendered.next().unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Run the command of a variable of `task`, or a global variable, unless
+    /// it has already run
+    async fn compute(
+        &mut self,
+        task: Option<(&str, &Task)>,
+        name: &str,
+        var: &Var,
+    ) -> Result<(), TaskRunnerError> {
+        let Var::Command(command) = var else {
+            return Ok(());
+        };
+        let key = (task.map(|(task_name, _)| task_name.to_string()), name.to_string());
+        if self.computed.contains_key(&key) {
+            return Ok(());
+        }
+
+        // Global variables use the global shell and run in the configuration's
+        // directory, task variables in the directory of the file defining the task
+        let config = self.config;
+        let (shell, dir) = match task {
+            Some((_, task)) => (task.shell.as_deref(), &task.base_dir),
+            None => (None, &config.dir),
+        };
+        let shell = Shell::resolve(shell, config.shell.as_deref())
+            .map_err(TaskRunnerError::ConfigParseError)?;
+
+        if self.env.is_none() {
+            self.env = Some(dotenv::resolve_config(config)?.into_iter()
+                .map(|(key, (value, _))| (key, value))
+                .collect());
+        }
+        let env = self.env.as_ref().unwrap();
+
+        let value = Value::String(run_command(name, &command.sh, &shell, dir, env).await?);
+        self.computed.insert(key, value);
+        Ok(())
+    }
+}
+
+/// Run the command of variable `name` in `dir` with environment `env`, and
+/// return its trimmed stdout
+async fn run_command(
+    name: &str,
+    command: &str,
+    shell: &Shell,
+    dir: &Path,
+    env: &HashMap<String, String>,
+) -> Result<String, TaskRunnerError> {
+    let (program, args) = shell.build(command);
+    let mut cmd = Command::new(&program);
+    cmd.args(&args)
+        .env_clear()
+        .envs(env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if !dir.as_os_str().is_empty() {
+        cmd.current_dir(dir);
+    }
+
+    let output = cmd.output().await
+        .map_err(|e| TaskRunnerError::TemplateError(
+            format!("command of variable '{}' could not be run: {}", name, e)
+        ))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("command of variable '{}' failed ({})", name, output.status);
+        if !stderr.trim().is_empty() {
+            message = format!("{}: {}", message, stderr.trim());
+        }
+        return Err(TaskRunnerError::TemplateError(message));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_task() {
+        let config: Config = serde_yaml::from_str(r#"
+vars:
+  image: registry.example.com/app
+  platforms: [linux/amd64, linux/arm64]
+  revision: { sh: "echo abc123" }
+  unused: { sh: "exit 1" }
+tasks:
+  docker:
+    vars:
+      image: local/app
+    env:
+      TAG: "{{ revision }}"
+    commands:
+      - "docker build -t {{ image }}:{{ revision }} --label v={{ params.v | default('dev') }} --platform {{ platforms | join(',') }}"
+"#).unwrap();
+
+        let mut vars = RunVars::new(&config);
+        let mut task = config.tasks["docker"].clone();
+        vars.render_task("docker", &mut task, &HashMap::new()).await.unwrap();
+
+        assert_eq!(
+            task.commands[0].run(),
+            "docker build -t local/app:abc123 --label v=dev --platform linux/amd64,linux/arm64"
+        );
+        assert_eq!(task.env["TAG"], "abc123");
+        assert!(vars.computed.contains_key(&(None, "revision".to_string())));
+        assert!(!vars.computed.contains_key(&(None, "unused".to_string())));
+
+        // Variables are not exported to the environment of commands
+        assert!(!task.env.contains_key("image"));
+    }
+
+    #[tokio::test]
+    async fn test_command_runs_in_config_dir_with_config_env() {
+        let dir = std::env::temp_dir().join(format!("task-runner-vars-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("VERSION"), "1.2.3\n").unwrap();
+
+        let mut config: Config = serde_yaml::from_str(r#"
+env:
+  CHANNEL: beta
+vars:
+  version: { sh: "echo $(cat VERSION)-$CHANNEL" }
+tasks:
+  release:
+    commands: ["echo {{ version }}"]
+"#).unwrap();
+        config.dir = dir.clone();
+
+        let mut task = config.tasks["release"].clone();
+        RunVars::new(&config).render_task("release", &mut task, &HashMap::new()).await.unwrap();
+        assert_eq!(task.commands[0].run(), "echo 1.2.3-beta");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+use crate::config::{Config, LoadOptions};
+use crate::dotenv;
+use crate::error::TaskRunnerError;
+use crate::local;
+use crate::executor::{RunOptions, TaskExecutor};
+use crate::process::{SignalListener, StopSignal};
+use colored::*;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+
+/// How long the file system must be quiet before changes trigger a new run
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Directories whose changes never trigger a run
+const ALWAYS_IGNORED: [&str; 2] = [".git", ".task-runner"];
+
+type WatchEvent = notify::Result<notify::Event>;
+
+/// What a batch of file system events changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    /// The configuration file, or a file it includes, changed
+    Config,
+    /// Files watched by the tasks changed
+    Files(Vec<PathBuf>),
+}
+
+/// What a file change must match to re-run the tasks
+#[derive(Debug)]
+enum Trigger {
+    /// A task's `sources` pattern
+    Pattern(glob::Pattern),
+    /// A task without sources, watched through its working directory
+    Dir(PathBuf),
+}
+
+/// The files watched for a set of tasks
+#[derive(Debug)]
+struct WatchSet {
+    root: PathBuf,
+    /// The configuration file and the files it includes
+    config_files: Vec<PathBuf>,
+    /// Directories watched recursively
+    dirs: Vec<PathBuf>,
+    triggers: Vec<Trigger>,
+    /// The tasks' own `outputs`, which must not re-trigger them
+    outputs: Vec<glob::Pattern>,
+    gitignore: Gitignore,
+}
+
+impl WatchSet {
+    /// Work out what to watch for `task_names` and their dependencies.
+    ///
+    /// Relative paths are resolved against `root`, whose `.gitignore` lists
+    /// files to ignore.
+    fn new(
+        config: &Config,
+        task_names: &[String],
+        root: &Path,
+        config_path: &Path,
+    ) -> Result<Self, TaskRunnerError> {
+        let mut dirs = Vec::new();
+        let mut triggers = Vec::new();
+        let mut outputs = Vec::new();
+
+        for task_name in config.get_execution_order(task_names)? {
+            let task = config.get_task(&task_name).unwrap();
+
+            let env_vars: HashMap<String, String> = dotenv::resolve(config, task)?
+                .into_iter()
+                .map(|(key, (value, _))| (key, value))
+                .collect();
+
+            let base = config.working_dir(task, &env_vars)?
+                .map(|dir| root.join(dir))
+                .unwrap_or_else(|| root.to_path_buf());
+
+            if task.sources.is_empty() {
+                dirs.push(existing_ancestor(&base));
+                triggers.push(Trigger::Dir(base.clone()));
+            } else {
+                for source in &task.sources {
+                    dirs.push(existing_ancestor(&base.join(literal_prefix(source))));
+                    triggers.push(Trigger::Pattern(absolute_pattern(&base, source)?));
+                }
+            }
+
+            for output in &task.outputs {
+                outputs.push(absolute_pattern(&base, output)?);
+            }
+        }
+
+        // Watching a directory covers everything below it
+        dirs.sort();
+        dirs.dedup();
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            if !roots.iter().any(|root| dir.starts_with(root)) {
+                roots.push(dir);
+            }
+        }
+
+        let mut builder = GitignoreBuilder::new(root);
+        let gitignore_path = root.join(".gitignore");
+        if gitignore_path.exists() {
+            if let Some(e) = builder.add(&gitignore_path) {
+                return Err(TaskRunnerError::WatchError(
+                    format!("Invalid {}: {}", gitignore_path.display(), e)
+                ));
+            }
+        }
+        let gitignore = builder.build()
+            .map_err(|e| TaskRunnerError::WatchError(e.to_string()))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            config_files: std::iter::once(config_path.to_path_buf())
+                .chain(local::candidates(config_path))
+                .chain(config.parent_configs.iter().flat_map(|path| {
+                    std::iter::once(path.clone()).chain(local::candidates(path))
+                }))
+                .chain(config.included_files.iter().flat_map(|path| {
+                    std::iter::once(path.clone()).chain(local::candidates(path))
+                }))
+                .chain(config.user_config.clone())
+                .collect(),
+            dirs: roots,
+            triggers,
+            outputs,
+            gitignore,
+        })
+    }
+
+    /// Start watching, sending events to `tx` until the returned watcher is dropped
+    fn start(&self, tx: UnboundedSender<WatchEvent>) -> Result<RecommendedWatcher, TaskRunnerError> {
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .map_err(|e| TaskRunnerError::WatchError(e.to_string()))?;
+
+        for dir in &self.dirs {
+            watcher.watch(dir, RecursiveMode::Recursive)
+                .map_err(|e| TaskRunnerError::WatchError(format!("{}: {}", dir.display(), e)))?;
+        }
+
+        // Editors often replace files rather than writing to them, so watch their directories
+        let mut config_dirs: Vec<&Path> = self.config_files.iter()
+            .map(|file| file.parent().unwrap_or(&self.root))
+            .collect();
+        config_dirs.sort();
+        config_dirs.dedup();
+        for config_dir in config_dirs {
+            watcher.watch(config_dir, RecursiveMode::NonRecursive)
+                .map_err(|e| TaskRunnerError::WatchError(format!("{}: {}", config_dir.display(), e)))?;
+        }
+
+        Ok(watcher)
+    }
+
+    /// Whether a change to `path` should re-run the tasks
+    fn is_relevant(&self, path: &Path) -> bool {
+        if let Ok(relative) = path.strip_prefix(&self.root) {
+            let first = relative.components().next();
+            if first.is_some_and(|first| ALWAYS_IGNORED.iter().any(|dir| first.as_os_str() == *dir)) {
+                return false;
+            }
+            if self.gitignore.matched_path_or_any_parents(relative, path.is_dir()).is_ignore() {
+                return false;
+            }
+        }
+
+        if self.outputs.iter().any(|pattern| pattern.matches_path(path)) {
+            return false;
+        }
+
+        self.triggers.iter().any(|trigger| match trigger {
+            Trigger::Pattern(pattern) => pattern.matches_path(path),
+            Trigger::Dir(dir) => path.starts_with(dir),
+        })
+    }
+
+    /// Classify a file system event, ignoring those that don't matter
+    fn classify(&self, event: WatchEvent) -> Option<Change> {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("{} Watch error: {}", "⚠️".yellow(), e);
+                return None;
+            }
+        };
+
+        if matches!(event.kind, EventKind::Access(_)) {
+            return None;
+        }
+
+        if event.paths.iter().any(|path| self.config_files.contains(path)) {
+            return Some(Change::Config);
+        }
+
+        let paths: Vec<PathBuf> = event.paths.into_iter()
+            .filter(|path| self.is_relevant(path))
+            .collect();
+        (!paths.is_empty()).then_some(Change::Files(paths))
+    }
+}
+
+/// The directories of a glob pattern before its first wildcard
+fn literal_prefix(pattern: &str) -> PathBuf {
+    let components: Vec<Component> = Path::new(pattern).components().collect();
+    let mut prefix = PathBuf::new();
+
+    // The last component names files, even when it has no wildcard
+    for component in &components[..components.len().saturating_sub(1)] {
+        if component.as_os_str().to_string_lossy().contains(['*', '?', '[', '{']) {
+            break;
+        }
+        prefix.push(component);
+    }
+
+    prefix
+}
+
+/// Closest existing directory at or above `dir`
+fn existing_ancestor(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|dir| dir.is_dir())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+fn absolute_pattern(base: &Path, pattern: &str) -> Result<glob::Pattern, TaskRunnerError> {
+    glob::Pattern::new(&base.join(pattern).to_string_lossy())
+        .map_err(|e| TaskRunnerError::ConfigParseError(
+            format!("Invalid glob pattern '{}': {}", pattern, e)
+        ))
+}
+
+/// Collect changes until the file system has been quiet for `DEBOUNCE`
+async fn debounce(
+    rx: &mut UnboundedReceiver<WatchEvent>,
+    watch_set: &WatchSet,
+    first: Change,
+) -> Change {
+    let mut change = first;
+
+    while let Ok(Some(event)) = timeout(DEBOUNCE, rx.recv()).await {
+        change = match (change, watch_set.classify(event)) {
+            (Change::Config, _) | (_, Some(Change::Config)) => Change::Config,
+            (Change::Files(mut paths), Some(Change::Files(more))) => {
+                paths.extend(more);
+                Change::Files(paths)
+            }
+            (change, None) => change,
+        };
+    }
+
+    change
+}
+
+/// Run the tasks, then run them again whenever their sources change.
+///
+/// A change cancels the run in progress before starting a new one, and a change
+/// to the configuration file reloads it with the same `load_options`. Only a signal ends watching.
+pub async fn watch(
+    config_path: Option<&str>,
+    load_options: &LoadOptions,
+    task_names: &[String],
+    options: &RunOptions,
+) -> Result<(), TaskRunnerError> {
+    // Paths are resolved against the configuration file's directory, wherever it was found from
+    let config_path = std::env::current_dir()?.join(Config::locate(config_path)?);
+    let root = config_path.parent().unwrap_or(Path::new("/")).to_path_buf();
+    let mut config = Config::load_from_file_with_options(&config_path, load_options)?;
+
+    let mut signals = SignalListener::new()?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let task_names: Arc<[String]> = task_names.into();
+
+    loop {
+        let watch_set = WatchSet::new(&config, &task_names, &root, &config_path)?;
+        let _watcher = watch_set.start(tx.clone())?;
+
+        loop {
+            let executor = Arc::new(TaskExecutor::new(config.clone()));
+            let interrupt = executor.interrupt_handle();
+            let mut run: JoinHandle<Result<(), TaskRunnerError>> = {
+                let (task_names, options) = (task_names.clone(), options.clone());
+                tokio::spawn(async move { executor.run_tasks(&task_names, &options).await })
+            };
+            let mut finished = false;
+
+            // Wait for the first relevant change, reporting the run when it finishes
+            let change = loop {
+                tokio::select! {
+                    result = &mut run, if !finished => {
+                        finished = true;
+                        if let Err(e) = result.expect("task run panicked") {
+                            eprintln!("{} {}", "Error:".red().bold(), e);
+                        }
+                        println!();
+                        println!("{} Watching for changes...", "👀".blue());
+                    }
+                    signal = signals.recv() => {
+                        if !finished {
+                            // Further signals force the commands to stop
+                            interrupt.trigger(signal);
+                            loop {
+                                tokio::select! {
+                                    _ = &mut run => break,
+                                    signal = signals.recv() => { interrupt.trigger(signal); }
+                                }
+                            }
+                        }
+                        return Err(TaskRunnerError::Interrupted(format!("received {}", signal)));
+                    }
+                    Some(event) = rx.recv() => {
+                        if let Some(change) = watch_set.classify(event) {
+                            break change;
+                        }
+                    }
+                }
+            };
+
+            let change = debounce(&mut rx, &watch_set, change).await;
+
+            if !finished {
+                interrupt.trigger(StopSignal::Terminate);
+                let _ = run.await;
+            }
+
+            match change {
+                Change::Config => {
+                    let shown = config_path.strip_prefix(&root).unwrap_or(&config_path);
+                    println!("{} {} changed, reloading...", "🔄".blue(), shown.display());
+                    match Config::load_from_file_with_options(&config_path, load_options) {
+                        Ok(reloaded) => {
+                            config = reloaded;
+                            break;
+                        }
+                        // Keep going with the previous configuration until the file is fixed
+                        Err(e) => eprintln!("{} {}", "Error:".red().bold(), e),
+                    }
+                }
+                Change::Files(mut paths) => {
+                    paths.sort();
+                    paths.dedup();
+                    let first = paths[0].strip_prefix(&root).unwrap_or(&paths[0]);
+                    if paths.len() == 1 {
+                        println!("{} {} changed, restarting...", "🔄".blue(), first.display());
+                    } else {
+                        println!("{} {} and {} more changed, restarting...",
+                            "🔄".blue(), first.display(), paths.len() - 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix("src/**/*.rs"), PathBuf::from("src"));
+        assert_eq!(literal_prefix("src/lib/*.ts"), PathBuf::from("src/lib"));
+        assert_eq!(literal_prefix("Cargo.toml"), PathBuf::new());
+        assert_eq!(literal_prefix("*/main.go"), PathBuf::new());
+    }
+
+    #[test]
+    fn test_watch_set_filters_changes() {
+        let root = std::env::temp_dir().join(format!("task-runner-watch-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+
+        let mut config: Config = serde_yaml::from_str("tasks: {}").unwrap();
+        config.tasks.insert(
+            "build".to_string(),
+            Task::new(vec!["make".to_string()])
+                .with_sources(vec!["src/**/*".to_string()])
+                .with_outputs(vec!["src/gen/*".to_string()]),
+        );
+        config.tasks.insert(
+            "docs".to_string(),
+            Task::new(vec!["mkdocs build".to_string()]),
+        );
+        config.tasks.get_mut("docs").unwrap().working_dir = Some("docs".to_string());
+
+        let config_path = root.join("task-runner.yaml");
+        let watch_set = WatchSet::new(&config, &["build".to_string()], &root, &config_path).unwrap();
+
+        assert_eq!(watch_set.dirs, vec![root.join("src")]);
+        assert!(watch_set.is_relevant(&root.join("src/main.c")));
+        assert!(!watch_set.is_relevant(&root.join("src/debug.log")));
+        assert!(!watch_set.is_relevant(&root.join("src/gen/out.c")));
+        assert!(!watch_set.is_relevant(&root.join("README.md")));
+
+        let docs = WatchSet::new(&config, &["docs".to_string()], &root, &config_path).unwrap();
+        assert!(docs.is_relevant(&root.join("docs/index.md")));
+        assert!(!docs.is_relevant(&root.join("src/main.c")));
+
+        let event = notify::Event::new(EventKind::Any).add_path(config_path.clone());
+        assert_eq!(watch_set.classify(Ok(event)), Some(Change::Config));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}